@@ -1,3 +1,28 @@
+use std::process::Command;
+
 fn main() {
+  if std::env::var_os("CARGO_FEATURE_LIBRIME").is_some() {
+    link_librime();
+  }
   tauri_build::build()
 }
+
+/// Add librime's directory to the link search path for the `librime` feature:
+/// `RIME_LIB_DIR` if set, else whatever pkg-config reports for `rime`.
+fn link_librime() {
+  println!("cargo:rerun-if-env-changed=RIME_LIB_DIR");
+  if let Some(dir) = std::env::var_os("RIME_LIB_DIR") {
+    println!("cargo:rustc-link-search=native={}", std::path::Path::new(&dir).display());
+    return;
+  }
+  match Command::new("pkg-config").args(["--libs-only-L", "rime"]).output() {
+    Ok(output) if output.status.success() => {
+      for flag in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+        if let Some(dir) = flag.strip_prefix("-L") {
+          println!("cargo:rustc-link-search=native={}", dir);
+        }
+      }
+    }
+    _ => println!("cargo:warning=pkg-config 未找到 librime, 请将 RIME_LIB_DIR 设为 librime 所在目录"),
+  }
+}
@@ -1,5 +1,7 @@
 use serde::Serialize;
-use crate::rime::{paths, reader, writer, schemas, deploy, phrases};
+use std::path::{Path, PathBuf};
+use crate::rime::error::RimeError;
+use crate::rime::{algebra, paths, git, history, profiles, reader, writer, merge, schemas, deploy, frontend, phrases, sync, userdb, dict, dict_index, schema_list};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -7,9 +9,18 @@ pub struct HealthData {
     platform: String,
     config_dir: String,
     config_dir_exists: bool,
+    shared_dir: Option<String>,
+    profile: Option<String>,
+    frontend: Option<FrontendInfo>,
     installation: Option<InstallationInfo>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct FrontendInfo {
+    id: String,
+    name: String,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InstallationInfo {
@@ -29,8 +40,8 @@ pub fn get_health() -> HealthData {
         let install_path = paths::get_config_path("installation.yaml");
         std::fs::read_to_string(&install_path)
             .ok()
-            .map(|content| {
-                let parsed = crate::rime::yaml_utils::parse_yaml(&content);
+            .and_then(|content| crate::rime::yaml_utils::parse_yaml(&content).ok())
+            .map(|parsed| {
                 let obj = parsed.as_object();
                 InstallationInfo {
                     distribution_name: obj
@@ -67,76 +78,466 @@ pub fn get_health() -> HealthData {
         "unknown"
     };
 
+    let frontend = frontend::detect_frontend().map(|f| FrontendInfo {
+        id: f.id().into(),
+        name: f.name().into(),
+    });
+
+    let profile = profiles::load_registry().ok().and_then(|registry| {
+        let active = registry.active?;
+        registry
+            .profiles
+            .into_iter()
+            .find(|p| p.id == active)
+            .map(|p| p.name)
+    });
+
     HealthData {
         platform: platform.into(),
         config_dir: config_dir_str,
         config_dir_exists,
+        shared_dir: paths::get_shared_data_dir().map(|d| d.to_string_lossy().to_string()),
+        profile,
+        frontend,
         installation,
     }
 }
 
+/// Every Rime user directory found on this machine, best candidate first.
+#[tauri::command]
+pub fn discover_config_dirs() -> Vec<paths::CandidateDir> {
+    paths::discover_config_dirs()
+}
+
 #[derive(Debug, Serialize)]
 pub struct SchemasResponse {
     schemas: Vec<schemas::SchemaMetadata>,
+    errors: Vec<RimeError>,
 }
 
 #[tauri::command]
 pub fn get_schemas() -> SchemasResponse {
-    SchemasResponse {
-        schemas: schemas::discover_schemas(),
-    }
+    let (schemas, errors) = schemas::discover_schemas();
+    SchemasResponse { schemas, errors }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigResponse {
     base: serde_json::Value,
+    /// Where `base` was found; `None` when it exists in neither layer.
+    base_layer: Option<paths::Layer>,
     custom_patch: serde_json::Value,
 }
 
 #[tauri::command]
-pub fn read_config(basename: String, config_type: Option<String>) -> ConfigResponse {
+pub fn read_config(basename: String, config_type: Option<String>) -> Result<ConfigResponse, RimeError> {
     let config_type = config_type.unwrap_or_else(|| "all".into());
 
-    match config_type.as_str() {
-        "base" => ConfigResponse {
-            base: reader::read_base_config(&basename),
-            custom_patch: serde_json::Value::Object(serde_json::Map::new()),
-        },
+    Ok(match config_type.as_str() {
+        "base" => {
+            let (base, base_layer) = reader::read_base_config(&basename)?;
+            ConfigResponse {
+                base,
+                base_layer,
+                custom_patch: serde_json::Value::Object(serde_json::Map::new()),
+            }
+        }
         "custom" => ConfigResponse {
             base: serde_json::Value::Object(serde_json::Map::new()),
-            custom_patch: reader::read_custom_config(&basename),
+            base_layer: None,
+            custom_patch: reader::read_custom_config(&basename)?,
         },
-        _ => ConfigResponse {
-            base: reader::read_base_config(&basename),
-            custom_patch: reader::read_custom_config(&basename),
-        },
-    }
+        _ => {
+            let (base, base_layer) = reader::read_base_config(&basename)?;
+            ConfigResponse {
+                base,
+                base_layer,
+                custom_patch: reader::read_custom_config(&basename)?,
+            }
+        }
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfigResponse {
+    config: serde_json::Value,
+    sources: Vec<merge::ConfigSource>,
 }
 
+/// Compile the config exactly as Rime will see it after deploy.
 #[tauri::command]
-pub fn write_config(basename: String, patch: serde_json::Value) -> Result<(), String> {
+pub fn read_effective_config(basename: String) -> Result<EffectiveConfigResponse, RimeError> {
+    let merged = merge::compile_config(&basename)?;
+    Ok(EffectiveConfigResponse {
+        config: merged.config,
+        sources: merged.sources,
+    })
+}
+
+#[tauri::command]
+pub fn write_config(basename: String, patch: serde_json::Value) -> Result<(), RimeError> {
     writer::write_custom_config(&basename, &patch)
 }
 
+/// Run `f` on the blocking thread pool. Sync commands run on the main thread,
+/// so anything that waits on a build would freeze the window.
+async fn run_blocking<T, F>(f: F) -> Result<T, RimeError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| RimeError::invalid(&paths::get_rime_config_dir(), format!("后台任务失败: {}", e)))
+}
+
+/// Redeploy through the detected frontend, optionally syncing user data afterwards.
+#[tauri::command]
+pub async fn deploy(sync: Option<bool>) -> Result<deploy::DeployReport, RimeError> {
+    run_blocking(move || deploy::trigger_deploy(sync.unwrap_or(false))).await
+}
+
+/// Compile a config directory with `rime_deployer`, no input method needed.
+#[tauri::command]
+pub async fn deploy_offline(
+    user_dir: Option<String>,
+    shared_dir: Option<String>,
+    staging_dir: Option<String>,
+) -> Result<deploy::DeployReport, RimeError> {
+    run_blocking(move || {
+        deploy::deploy_offline(
+            user_dir.map(PathBuf::from),
+            shared_dir.map(PathBuf::from),
+            staging_dir.map(PathBuf::from),
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn list_phrase_tables() -> Vec<phrases::PhraseTable> {
+    phrases::list_tables()
+}
+
+/// `table` defaults to `custom_phrase`.
+#[tauri::command]
+pub fn read_phrases(table: Option<String>) -> Result<phrases::PhrasesData, RimeError> {
+    phrases::read_phrases(table.as_deref().unwrap_or(phrases::DEFAULT_TABLE))
+}
+
+#[tauri::command]
+pub fn write_phrases(table: Option<String>, data: phrases::PhrasesData) -> Result<(), RimeError> {
+    phrases::write_phrases(table.as_deref().unwrap_or(phrases::DEFAULT_TABLE), &data)
+}
+
+/// Check phrases before saving; see `phrases::validate`.
+#[tauri::command]
+pub fn validate_phrases(
+    table: Option<String>,
+    schema_id: Option<String>,
+    data: phrases::PhrasesData,
+) -> Result<phrases::PhraseValidation, RimeError> {
+    phrases::validate(
+        table.as_deref().unwrap_or(phrases::DEFAULT_TABLE),
+        schema_id.as_deref(),
+        &data,
+    )
+}
+
+/// Dry run of `import_phrases`: the converted entries and how they compare
+/// with the table. `format` is detected when omitted.
+#[tauri::command]
+pub fn preview_phrase_import(
+    table: Option<String>,
+    path: String,
+    format: Option<phrases::import::ImportFormat>,
+) -> Result<phrases::import::ImportPreview, RimeError> {
+    phrases::import::preview(table.as_deref().unwrap_or(phrases::DEFAULT_TABLE), Path::new(&path), format)
+}
+
+#[tauri::command]
+pub fn import_phrases(
+    table: Option<String>,
+    path: String,
+    format: Option<phrases::import::ImportFormat>,
+    replace_conflicts: Option<bool>,
+) -> Result<phrases::import::ImportPreview, RimeError> {
+    phrases::import::import(
+        table.as_deref().unwrap_or(phrases::DEFAULT_TABLE),
+        Path::new(&path),
+        format,
+        replace_conflicts.unwrap_or(false),
+    )
+}
+
+#[tauri::command]
+pub fn export_phrases(
+    table: Option<String>,
+    format: phrases::export::ExportFormat,
+    path: String,
+    schema_id: Option<String>,
+) -> Result<phrases::export::ExportSummary, RimeError> {
+    phrases::export::export(
+        table.as_deref().unwrap_or(phrases::DEFAULT_TABLE),
+        format,
+        Path::new(&path),
+        schema_id.as_deref(),
+    )
+}
+
+#[tauri::command]
+pub fn check_schema_dependencies(schema_id: String) -> Result<schema_list::DependencyReport, RimeError> {
+    schema_list::check_dependencies(&schema_id)
+}
+
+/// Blocked, not failed, when the schema needs files that aren't installed.
+#[tauri::command]
+pub fn enable_schema(schema_id: String, position: Option<usize>) -> Result<schema_list::SchemaListChange, RimeError> {
+    schema_list::enable_schema(&schema_id, position)
+}
+
+#[tauri::command]
+pub fn disable_schema(schema_id: String) -> Result<schema_list::SchemaListChange, RimeError> {
+    schema_list::disable_schema(&schema_id)
+}
+
+#[tauri::command]
+pub fn reorder_schemas(schema_ids: Vec<String>) -> Result<schema_list::SchemaListChange, RimeError> {
+    schema_list::reorder_schemas(&schema_ids)
+}
+
+#[tauri::command]
+pub fn get_schema_algebra(schema_id: String) -> Result<algebra::SchemaAlgebra, RimeError> {
+    algebra::read_algebra(&schema_id)
+}
+
+/// `reverse` defaults to `enabled`, so a pair is normally toggled both ways.
+#[tauri::command]
+pub fn set_fuzzy_pinyin(
+    schema_id: String,
+    pair: String,
+    enabled: bool,
+    reverse: Option<bool>,
+) -> Result<algebra::SchemaAlgebra, RimeError> {
+    algebra::set_fuzzy(&schema_id, &pair, enabled, reverse)
+}
+
+/// Run a schema's algebra, or `rules` in its place, over its dictionary's
+/// syllables or over `syllables` if given. Nothing is written.
+#[tauri::command]
+pub fn simulate_algebra(
+    schema_id: String,
+    rules: Option<Vec<String>>,
+    syllables: Option<Vec<String>>,
+) -> Result<algebra::simulate::AlgebraSimulation, RimeError> {
+    algebra::simulate::simulate(&schema_id, rules, syllables)
+}
+
+#[tauri::command]
+pub fn list_double_pinyin_layouts() -> Vec<algebra::double_pinyin::DoublePinyinScheme> {
+    algebra::double_pinyin::schemes()
+}
+
+#[tauri::command]
+pub fn set_double_pinyin(schema_id: String, layout: String) -> Result<algebra::SchemaAlgebra, RimeError> {
+    algebra::double_pinyin::set_double_pinyin(&schema_id, &layout)
+}
+
+#[tauri::command]
+pub fn list_profiles() -> Result<profiles::ProfileRegistry, RimeError> {
+    profiles::load_registry()
+}
+
+#[tauri::command]
+pub fn add_profile(name: String, config_dir: String) -> Result<profiles::Profile, RimeError> {
+    profiles::add_profile(&name, &config_dir)
+}
+
+#[tauri::command]
+pub fn rename_profile(id: String, name: String) -> Result<(), RimeError> {
+    profiles::rename_profile(&id, &name)
+}
+
+#[tauri::command]
+pub fn remove_profile(id: String) -> Result<(), RimeError> {
+    profiles::remove_profile(&id)
+}
+
+/// Switch the active profile; `None` returns to the detected default directory.
+#[tauri::command]
+pub fn activate_profile(id: Option<String>) -> Result<(), RimeError> {
+    profiles::activate_profile(id.as_deref())
+}
+
+#[tauri::command]
+pub fn list_snapshots(filename: String) -> Result<Vec<history::Snapshot>, RimeError> {
+    history::list_snapshots(&filename)
+}
+
+/// Diff a snapshot against the file's current content.
+#[tauri::command]
+pub fn diff_snapshot(filename: String, id: String) -> Result<Vec<history::DiffLine>, RimeError> {
+    history::diff_snapshot(&filename, &id)
+}
+
+#[tauri::command]
+pub fn restore_snapshot(filename: String, id: String) -> Result<(), RimeError> {
+    history::restore_snapshot(&filename, &id)
+}
+
+#[tauri::command]
+pub fn git_status() -> git::GitStatus {
+    git::status()
+}
+
+/// Make the config dir a git repository; later saves are committed automatically.
+#[tauri::command]
+pub fn git_init() -> Result<(), RimeError> {
+    git::init_repo()
+}
+
+#[tauri::command]
+pub fn git_log(limit: Option<usize>) -> Result<Vec<git::CommitInfo>, RimeError> {
+    git::log(limit.unwrap_or(100))
+}
+
+#[tauri::command]
+pub fn git_diff(id: String) -> Result<Vec<git::FileDiff>, RimeError> {
+    git::diff_commit(&id)
+}
+
+#[tauri::command]
+pub fn git_revert(id: String) -> Result<(), RimeError> {
+    git::revert_commit(&id)
+}
+
+#[tauri::command]
+pub fn get_sync_settings() -> Result<sync::SyncSettings, RimeError> {
+    sync::read_settings()
+}
+
+/// Edit `installation_id` / `sync_dir`; an empty `sync_dir` restores the default.
+#[tauri::command]
+pub fn update_sync_settings(
+    installation_id: Option<String>,
+    sync_dir: Option<String>,
+) -> Result<sync::SyncSettings, RimeError> {
+    sync::update_settings(installation_id.as_deref(), sync_dir.as_deref())
+}
+
+#[tauri::command]
+pub fn list_sync_installations() -> Result<Vec<sync::InstallationFolder>, RimeError> {
+    sync::list_installations()
+}
+
+/// Sync user data through `sync_dir` without redeploying.
+#[tauri::command]
+pub async fn sync_user_data() -> Result<String, RimeError> {
+    run_blocking(|| {
+        let config_dir = paths::get_rime_config_dir();
+        let frontend = frontend::detect_frontend()
+            .ok_or_else(|| RimeError::invalid(&config_dir, "未找到支持的输入框架"))?;
+        frontend
+            .sync(&config_dir)
+            .map_err(|e| RimeError::invalid(&config_dir, e))
+    })
+    .await?
+}
+
 #[derive(Debug, Serialize)]
-pub struct DeployResponse {
-    success: bool,
-    message: String,
+pub struct UserDbListResponse {
+    dictionaries: Vec<userdb::UserDbInfo>,
+    errors: Vec<RimeError>,
+}
+
+/// User dictionary snapshots in the config dir and every sync folder.
+#[tauri::command]
+pub fn list_userdbs() -> UserDbListResponse {
+    let (dictionaries, errors) = userdb::list_dictionaries();
+    UserDbListResponse { dictionaries, errors }
+}
+
+#[tauri::command]
+pub fn read_userdb(
+    path: String,
+    query: Option<String>,
+    include_deleted: Option<bool>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<userdb::UserDbPage, RimeError> {
+    userdb::read_entries(
+        &path,
+        query.as_deref(),
+        include_deleted.unwrap_or(false),
+        offset.unwrap_or(0),
+        limit.unwrap_or(200),
+    )
+}
+
+#[tauri::command]
+pub fn delete_userdb_entries(path: String, entries: Vec<userdb::UserDbKey>) -> Result<usize, RimeError> {
+    userdb::delete_entries(&path, &entries)
+}
+
+/// Merge snapshots from other machines into `target` with Rime's sync rules.
+#[tauri::command]
+pub fn merge_userdbs(target: String, sources: Vec<String>) -> Result<userdb::UserDbInfo, RimeError> {
+    userdb::merge_snapshots(&target, &sources)
+}
+
+#[derive(Debug, Serialize)]
+pub struct DictListResponse {
+    dictionaries: Vec<dict::DictInfo>,
+    errors: Vec<RimeError>,
+}
+
+#[tauri::command]
+pub fn list_dicts() -> DictListResponse {
+    let (dictionaries, errors) = dict::list_dictionaries();
+    DictListResponse { dictionaries, errors }
+}
+
+/// A dictionary followed by the tables it imports, recursively.
+#[tauri::command]
+pub fn resolve_dict_tables(name: String) -> Result<Vec<dict::DictInfo>, RimeError> {
+    dict::resolve_tables(&name)
+}
+
+/// Indexed search: code prefix, text substring and weight range, all optional.
+#[tauri::command]
+pub fn search_dict(
+    name: String,
+    query: Option<dict_index::DictQuery>,
+    include_imports: Option<bool>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<dict::DictPage, RimeError> {
+    dict::search(
+        &name,
+        &query.unwrap_or_default(),
+        include_imports.unwrap_or(true),
+        offset.unwrap_or(0),
+        limit.unwrap_or(200),
+    )
 }
 
 #[tauri::command]
-pub fn deploy() -> DeployResponse {
-    let (success, message) = deploy::trigger_deploy();
-    DeployResponse { success, message }
+pub fn add_dict_entry(name: String, entry: dict::DictEntry) -> Result<usize, RimeError> {
+    dict::add_entry(&name, &entry)
 }
 
 #[tauri::command]
-pub fn read_phrases() -> phrases::PhrasesData {
-    phrases::read_phrases()
+pub fn update_dict_entry(
+    name: String,
+    line: usize,
+    original: dict::DictEntry,
+    entry: dict::DictEntry,
+) -> Result<(), RimeError> {
+    dict::update_entry(&name, line, &original, &entry)
 }
 
 #[tauri::command]
-pub fn write_phrases(header: String, entries: Vec<phrases::PhraseEntry>) -> Result<(), String> {
-    phrases::write_phrases(&header, &entries)
+pub fn delete_dict_entry(name: String, line: usize, original: dict::DictEntry) -> Result<(), RimeError> {
+    dict::delete_entry(&name, line, &original)
 }
@@ -7,12 +7,56 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             commands::get_health,
+            commands::discover_config_dirs,
             commands::get_schemas,
             commands::read_config,
+            commands::read_effective_config,
             commands::write_config,
             commands::deploy,
+            commands::deploy_offline,
+            commands::list_phrase_tables,
             commands::read_phrases,
             commands::write_phrases,
+            commands::validate_phrases,
+            commands::preview_phrase_import,
+            commands::import_phrases,
+            commands::export_phrases,
+            commands::check_schema_dependencies,
+            commands::enable_schema,
+            commands::disable_schema,
+            commands::reorder_schemas,
+            commands::get_schema_algebra,
+            commands::set_fuzzy_pinyin,
+            commands::simulate_algebra,
+            commands::list_double_pinyin_layouts,
+            commands::set_double_pinyin,
+            commands::list_profiles,
+            commands::add_profile,
+            commands::rename_profile,
+            commands::remove_profile,
+            commands::activate_profile,
+            commands::list_snapshots,
+            commands::diff_snapshot,
+            commands::restore_snapshot,
+            commands::git_status,
+            commands::git_init,
+            commands::git_log,
+            commands::git_diff,
+            commands::git_revert,
+            commands::get_sync_settings,
+            commands::update_sync_settings,
+            commands::list_sync_installations,
+            commands::sync_user_data,
+            commands::list_userdbs,
+            commands::read_userdb,
+            commands::delete_userdb_entries,
+            commands::merge_userdbs,
+            commands::list_dicts,
+            commands::resolve_dict_tables,
+            commands::search_dict,
+            commands::add_dict_entry,
+            commands::update_dict_entry,
+            commands::delete_dict_entry,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,456 @@
+//! Double pinyin (双拼) layouts.
+//!
+//! A layout gives a key for `zh`, `ch` and `sh` and for every multi-letter
+//! final; other initials and single-letter finals are typed as themselves.
+//! The algebra is generated from the table: keys are written in upper case
+//! so later rules can't match them again, then lowered by a final `xlit`.
+//!
+//! A schema's layout is detected by running its algebra over the standard
+//! syllables and checking that it accepts every spelling a layout produces.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use super::simulate::run;
+use super::{effective_algebra, is_fuzzy_rule, patches_key, read_algebra, SchemaAlgebra, ALGEBRA_KEY};
+use crate::rime::error::RimeError;
+use crate::rime::reader::read_custom_config;
+use crate::rime::writer::write_custom_config;
+
+const LETTERS: &str = "zyxwvutsrqponmlkjihgfedcba";
+const ALPHABET_KEY: &str = "speller/alphabet";
+const INITIALS_KEY: &str = "speller/initials";
+const PREEDIT_KEY: &str = "translator/preedit_format";
+
+/// How syllables without an initial (`a`, `ang`, `er`...) are typed.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ZeroInitial {
+    /// The final's first letter stands in for the initial: `a` is `aa`,
+    /// `ang` is `a` plus the key of `ang`; two-letter finals are typed as is.
+    Doubled,
+    /// `o` is the initial: `a` is `oa`, `ang` is `o` plus the key of `ang`.
+    LeadingO,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoublePinyinLayout {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub zh: char,
+    pub ch: char,
+    pub sh: char,
+    pub zero_initial: ZeroInitial,
+    /// Finals and their keys. A final listed twice can be typed with either
+    /// key; the first is the usual one.
+    pub finals: &'static [(&'static str, char)],
+}
+
+/// A layout with the config generated from it.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoublePinyinScheme {
+    #[serde(flatten)]
+    pub layout: DoublePinyinLayout,
+    pub alphabet: String,
+    pub initials: String,
+    pub algebra: Vec<String>,
+}
+
+pub const LAYOUTS: &[DoublePinyinLayout] = &[
+    DoublePinyinLayout {
+        id: "flypy",
+        name: "小鹤双拼",
+        zh: 'v',
+        ch: 'i',
+        sh: 'u',
+        zero_initial: ZeroInitial::Doubled,
+        finals: &[
+            ("iu", 'q'), ("ei", 'w'), ("uan", 'r'), ("ue", 't'), ("ve", 't'), ("un", 'y'),
+            ("uo", 'o'), ("ie", 'p'), ("ong", 's'), ("iong", 's'), ("ai", 'd'), ("en", 'f'),
+            ("eng", 'g'), ("ang", 'h'), ("an", 'j'), ("ing", 'k'), ("uai", 'k'), ("iang", 'l'),
+            ("uang", 'l'), ("ou", 'z'), ("ia", 'x'), ("ua", 'x'), ("ao", 'c'), ("ui", 'v'),
+            ("in", 'b'), ("iao", 'n'), ("ian", 'm'),
+        ],
+    },
+    DoublePinyinLayout {
+        id: "ziranma",
+        name: "自然码",
+        zh: 'v',
+        ch: 'i',
+        sh: 'u',
+        zero_initial: ZeroInitial::Doubled,
+        finals: &[
+            ("iu", 'q'), ("ia", 'w'), ("ua", 'w'), ("uan", 'r'), ("ue", 't'), ("ve", 't'),
+            ("ing", 'y'), ("uai", 'y'), ("uo", 'o'), ("un", 'p'), ("ong", 's'), ("iong", 's'),
+            ("iang", 'd'), ("uang", 'd'), ("en", 'f'), ("eng", 'g'), ("ang", 'h'), ("an", 'j'),
+            ("ao", 'k'), ("ai", 'l'), ("ei", 'z'), ("ie", 'x'), ("iao", 'c'), ("ui", 'v'),
+            ("ou", 'b'), ("in", 'n'), ("ian", 'm'),
+        ],
+    },
+    DoublePinyinLayout {
+        id: "mspy",
+        name: "微软双拼",
+        zh: 'v',
+        ch: 'i',
+        sh: 'u',
+        zero_initial: ZeroInitial::LeadingO,
+        finals: &[
+            ("iu", 'q'), ("ia", 'w'), ("ua", 'w'), ("er", 'r'), ("uan", 'r'), ("ue", 't'),
+            ("ue", 'v'), ("ve", 't'), ("ve", 'v'), ("v", 'y'), ("uai", 'y'), ("uo", 'o'),
+            ("un", 'p'), ("ong", 's'), ("iong", 's'), ("iang", 'd'), ("uang", 'd'), ("en", 'f'),
+            ("eng", 'g'), ("ang", 'h'), ("an", 'j'), ("ao", 'k'), ("ai", 'l'), ("ing", ';'),
+            ("ei", 'z'), ("ie", 'x'), ("iao", 'c'), ("ui", 'v'), ("ou", 'b'), ("in", 'n'),
+            ("ian", 'm'),
+        ],
+    },
+    DoublePinyinLayout {
+        id: "sogou",
+        name: "搜狗双拼",
+        zh: 'v',
+        ch: 'i',
+        sh: 'u',
+        zero_initial: ZeroInitial::LeadingO,
+        finals: &[
+            ("iu", 'q'), ("ia", 'w'), ("ua", 'w'), ("er", 'r'), ("uan", 'r'), ("ue", 't'),
+            ("ve", 't'), ("v", 'y'), ("uai", 'y'), ("uo", 'o'), ("un", 'p'), ("ong", 's'),
+            ("iong", 's'), ("iang", 'd'), ("uang", 'd'), ("en", 'f'), ("eng", 'g'), ("ang", 'h'),
+            ("an", 'j'), ("ao", 'k'), ("ai", 'l'), ("ing", ';'), ("ei", 'z'), ("ie", 'x'),
+            ("iao", 'c'), ("ui", 'v'), ("ou", 'b'), ("in", 'n'), ("ian", 'm'),
+        ],
+    },
+    DoublePinyinLayout {
+        id: "abc",
+        name: "智能ABC",
+        zh: 'a',
+        ch: 'e',
+        sh: 'v',
+        zero_initial: ZeroInitial::LeadingO,
+        finals: &[
+            ("ei", 'q'), ("ian", 'w'), ("er", 'r'), ("iu", 'r'), ("iang", 't'), ("uang", 't'),
+            ("ing", 'y'), ("uo", 'o'), ("uan", 'p'), ("ong", 's'), ("iong", 's'), ("ia", 'd'),
+            ("ua", 'd'), ("en", 'f'), ("eng", 'g'), ("ang", 'h'), ("an", 'j'), ("iao", 'z'),
+            ("ao", 'k'), ("in", 'c'), ("uai", 'c'), ("ai", 'l'), ("ie", 'x'), ("ou", 'b'),
+            ("un", 'n'), ("ue", 'm'), ("ve", 'm'), ("ui", 'm'),
+        ],
+    },
+];
+
+/// The syllables of standard pinyin, spelled the way Rime dictionaries do
+/// (`lv`, `nve`).
+const SYLLABLES: &str = "a ai an ang ao \
+    ba bai ban bang bao bei ben beng bi bian biao bie bin bing bo bu \
+    ca cai can cang cao ce cen ceng cha chai chan chang chao che chen cheng chi chong chou chu chua \
+    chuai chuan chuang chui chun chuo ci cong cou cu cuan cui cun cuo \
+    da dai dan dang dao de dei den deng di dia dian diao die ding diu dong dou du duan dui dun duo \
+    e ei en eng er fa fan fang fei fen feng fo fou fu \
+    ga gai gan gang gao ge gei gen geng gong gou gu gua guai guan guang gui gun guo \
+    ha hai han hang hao he hei hen heng hong hou hu hua huai huan huang hui hun huo \
+    ji jia jian jiang jiao jie jin jing jiong jiu ju juan jue jun \
+    ka kai kan kang kao ke kei ken keng kong kou ku kua kuai kuan kuang kui kun kuo \
+    la lai lan lang lao le lei leng li lia lian liang liao lie lin ling liu lo long lou lu luan lun \
+    luo lv lve \
+    ma mai man mang mao me mei men meng mi mian miao mie min ming miu mo mou mu \
+    na nai nan nang nao ne nei nen neng ni nian niang niao nie nin ning niu nong nou nu nuan nun \
+    nuo nv nve o ou \
+    pa pai pan pang pao pei pen peng pi pian piao pie pin ping po pou pu \
+    qi qia qian qiang qiao qie qin qing qiong qiu qu quan que qun \
+    ran rang rao re ren reng ri rong rou ru rua ruan rui run ruo \
+    sa sai san sang sao se sen seng sha shai shan shang shao she shei shen sheng shi shou shu shua \
+    shuai shuan shuang shui shun shuo si song sou su suan sui sun suo \
+    ta tai tan tang tao te tei teng ti tian tiao tie ting tong tou tu tuan tui tun tuo \
+    wa wai wan wang wei wen weng wo wu xi xia xian xiang xiao xie xin xing xiong xiu xu xuan xue xun \
+    ya yan yang yao ye yi yin ying yo yong you yu yuan yue yun \
+    za zai zan zang zao ze zei zen zeng zha zhai zhan zhang zhao zhe zhei zhen zheng zhi zhong zhou \
+    zhu zhua zhuai zhuan zhuang zhui zhun zhuo zi zong zou zu zuan zui zun zuo";
+
+impl DoublePinyinLayout {
+    fn keys(&self) -> impl Iterator<Item = char> + '_ {
+        [self.zh, self.ch, self.sh].into_iter().chain(self.finals.iter().map(|(_, key)| *key))
+    }
+
+    pub fn alphabet(&self) -> String {
+        let mut alphabet = LETTERS.to_string();
+        for key in self.keys() {
+            if !alphabet.contains(key) {
+                alphabet.push(key);
+            }
+        }
+        alphabet
+    }
+
+    pub fn algebra(&self) -> Vec<String> {
+        let mut rules = vec!["erase/^xx$/".to_string()];
+        match self.zero_initial {
+            ZeroInitial::Doubled => {
+                rules.push("xform/^([aoe])$/$1$1/".to_string());
+                rules.push("xform/^([ae])ng$/$1$1ng/".to_string());
+            }
+            ZeroInitial::LeadingO => rules.push("xform/^([aoe].*)$/O$1/".to_string()),
+        }
+        for (initial, key) in [("zh", self.zh), ("ch", self.ch), ("sh", self.sh)] {
+            rules.push(format!("xform/^{}/{}/", initial, key.to_ascii_uppercase()));
+        }
+
+        let mut seen = HashSet::new();
+        for (final_, _) in self.finals {
+            if !seen.insert(*final_) {
+                continue;
+            }
+            let keys: Vec<char> = self
+                .finals
+                .iter()
+                .filter(|(f, _)| f == final_)
+                .map(|(_, key)| key.to_ascii_uppercase())
+                .collect();
+            for key in &keys[1..] {
+                rules.push(format!("derive/^(.){}$/$1{}/", final_, key));
+            }
+            rules.push(format!("xform/^(.){}$/$1{}/", final_, keys[0]));
+        }
+
+        let upper = LETTERS.to_ascii_uppercase();
+        rules.push(format!("xlit/{}/{}/", upper, LETTERS));
+        rules
+    }
+
+    pub fn scheme(&self) -> DoublePinyinScheme {
+        DoublePinyinScheme {
+            layout: self.clone(),
+            alphabet: self.alphabet(),
+            initials: LETTERS.to_string(),
+            algebra: self.algebra(),
+        }
+    }
+}
+
+pub fn schemes() -> Vec<DoublePinyinScheme> {
+    LAYOUTS.iter().map(|l| l.scheme()).collect()
+}
+
+fn syllables() -> Vec<String> {
+    SYLLABLES.split_whitespace().map(str::to_string).collect()
+}
+
+/// Every (spelling, syllable) pair an algebra produces.
+fn spelling_pairs(rules: &[String], syllables: &[String]) -> HashSet<(String, String)> {
+    let (_, spellings) = run(rules, syllables);
+    spellings
+        .into_iter()
+        .flat_map(|s| {
+            let spelling = s.spelling;
+            s.sources.into_iter().map(move |source| (spelling.clone(), source.syllable))
+        })
+        .collect()
+}
+
+/// The layout `rules` implement, if any. Fuzzy pinyin rules are ignored; of
+/// the layouts the algebra fully accepts, the one producing the most
+/// spellings wins, so a layout isn't mistaken for one with fewer alternatives.
+pub fn detect_layout(rules: &[String]) -> Option<&'static DoublePinyinLayout> {
+    let rules: Vec<String> = rules.iter().filter(|r| !is_fuzzy_rule(r)).cloned().collect();
+    if rules.is_empty() {
+        return None;
+    }
+    let syllables = syllables();
+    let accepted = spelling_pairs(&rules, &syllables);
+    LAYOUTS
+        .iter()
+        .filter_map(|layout| {
+            let pairs = spelling_pairs(&layout.algebra(), &syllables);
+            pairs.is_subset(&accepted).then_some((layout, pairs.len()))
+        })
+        .max_by_key(|(_, count)| *count)
+        .map(|(layout, _)| layout)
+}
+
+/// Switch `schema_id` to `layout_id` by patching the algebra, alphabet and
+/// initials. Fuzzy pinyin rules in the current algebra are kept ahead of the
+/// layout's rules. The schema's `preedit_format` belongs to its old spelling,
+/// so it is cleared and the preedit shows the keys typed.
+pub fn set_double_pinyin(schema_id: &str, layout_id: &str) -> Result<SchemaAlgebra, RimeError> {
+    let layout = LAYOUTS.iter().find(|l| l.id == layout_id).ok_or_else(|| {
+        RimeError::invalid(Path::new(&format!("{}.custom.yaml", schema_id)), format!("未知的双拼方案: {}", layout_id))
+    })?;
+
+    let fuzzy: Vec<String> = effective_algebra(schema_id)?.into_iter().filter(|r| is_fuzzy_rule(r)).collect();
+    let mut algebra = layout.algebra();
+    algebra.splice(1..1, fuzzy);
+
+    let mut patch = match read_custom_config(schema_id)? {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    patch.retain(|key, _| ![ALGEBRA_KEY, ALPHABET_KEY, INITIALS_KEY, PREEDIT_KEY].iter().any(|k| patches_key(key, k)));
+    patch.insert(ALPHABET_KEY.into(), Value::String(layout.alphabet()));
+    patch.insert(INITIALS_KEY.into(), Value::String(LETTERS.to_string()));
+    patch.insert(ALGEBRA_KEY.into(), Value::Array(algebra.into_iter().map(Value::String).collect()));
+    patch.insert(PREEDIT_KEY.into(), Value::Array(vec![]));
+    write_custom_config(schema_id, &Value::Object(patch))?;
+    read_algebra(schema_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `speller/algebra` of double_pinyin_flypy.schema.yaml from rime-double-pinyin.
+    const STOCK_FLYPY: &[&str] = &[
+        "erase/^xx$/",
+        "derive/^([jqxy])u$/$1v/",
+        "derive/^([aoe])([ioun])$/$1$1$2/",
+        "xform/^([aoe])(ng)?$/$1$1$2/",
+        "xform/iu$/Q/",
+        "xform/(.)ei$/$1W/",
+        "xform/uan$/R/",
+        "xform/[uv]e$/T/",
+        "xform/un$/Y/",
+        "xform/^sh/U/",
+        "xform/^ch/I/",
+        "xform/^zh/V/",
+        "xform/uo$/O/",
+        "xform/ie$/P/",
+        "xform/i?ong$/S/",
+        "xform/ing$|uai$/K/",
+        "xform/(.)ai$/$1D/",
+        "xform/(.)en$/$1F/",
+        "xform/(.)eng$/$1G/",
+        "xform/[iu]ang$/L/",
+        "xform/(.)ang$/$1H/",
+        "xform/ian$/M/",
+        "xform/(.)an$/$1J/",
+        "xform/(.)ou$/$1Z/",
+        "xform/[iu]a$/X/",
+        "xform/iao$/N/",
+        "xform/(.)ao$/$1C/",
+        "xform/ui$/V/",
+        "xform/in$/B/",
+        "xlit/QWRTYUIOPSDFGHJKLZXCVBNM/qwrtyuiopsdfghjklzxcvbnm/",
+    ];
+
+    /// `speller/algebra` of double_pinyin_mspy.schema.yaml from rime-double-pinyin.
+    const STOCK_MSPY: &[&str] = &[
+        "erase/^xx$/",
+        "derive/^([jqxy])u$/$1v/",
+        "derive/^([aoe].*)$/o$1/",
+        "xform/^([ae])(.*)$/$1$1$2/",
+        "xform/iu$/Q/",
+        "xform/[iu]a$/W/",
+        "xform/er$|[uv]an$/R/",
+        "xform/[uv]e$/T/",
+        "xform/v$|uai$/Y/",
+        "xform/^sh/U/",
+        "xform/^ch/I/",
+        "xform/^zh/V/",
+        "xform/uo$/O/",
+        "xform/[uv]n$/P/",
+        "xform/i?ong$/S/",
+        "xform/[iu]ang$/D/",
+        "xform/(.)en$/$1F/",
+        "xform/(.)eng$/$1G/",
+        "xform/(.)ang$/$1H/",
+        "xform/ian$/M/",
+        "xform/(.)an$/$1J/",
+        "xform/iao$/C/",
+        "xform/(.)ao$/$1K/",
+        "xform/(.)ai$/$1L/",
+        "xform/(.)ei$/$1Z/",
+        "xform/ie$/X/",
+        "xform/ui$/V/",
+        "derive/T$/V/",
+        "xform/(.)ou$/$1B/",
+        "xform/in$/N/",
+        "xform/ing$/;/",
+        "xlit/QWRTYUIOPSDFGHMJCKLZXVBN/qwrtyuiopsdfghmjcklzxvbn/",
+    ];
+
+    /// `speller/algebra` of double_pinyin_sogou.schema.yaml from rime-ice,
+    /// which writes keys as circled letters.
+    const STOCK_SOGOU: &[&str] = &[
+        "erase/^xx$/",
+        "derive/^([jqxy])u$/$1v/",
+        "derive/^([aoe].*)$/o$1/",
+        "xform/^([ae])(.*)$/$1$1$2/",
+        "xform/iu$/Ⓠ/",
+        "xform/[iu]a$/Ⓦ/",
+        "xform/er$|[uv]an$/Ⓡ/",
+        "xform/[uv]e$/Ⓣ/",
+        "xform/v$|uai$/Ⓨ/",
+        "xform/^sh/Ⓤ/",
+        "xform/^ch/Ⓘ/",
+        "xform/^zh/Ⓥ/",
+        "xform/uo$/Ⓞ/",
+        "xform/[uv]n$/Ⓟ/",
+        "xform/i?ong$/Ⓢ/",
+        "xform/[iu]ang$/Ⓓ/",
+        "xform/(.)en$/$1Ⓕ/",
+        "xform/(.)eng$/$1Ⓖ/",
+        "xform/(.)ang$/$1Ⓗ/",
+        "xform/ian$/Ⓜ/",
+        "xform/(.)an$/$1Ⓙ/",
+        "xform/iao$/Ⓒ/",
+        "xform/(.)ao$/$1Ⓚ/",
+        "xform/(.)ai$/$1Ⓛ/",
+        "xform/(.)ei$/$1Ⓩ/",
+        "xform/ie$/Ⓧ/",
+        "xform/ui$/Ⓥ/",
+        "xform/(.)ou$/$1Ⓑ/",
+        "xform/in$/Ⓝ/",
+        "xform/ing$/;/",
+        "xlit/ⓆⓌⓇⓉⓎⓊⒾⓄⓅⓈⒹⒻⒼⒽⓂⒿⒸⓀⓁⓏⓍⓋⒷⓃ/qwrtyuiopsdfghmjcklzxvbn/",
+    ];
+
+    fn layout(id: &str) -> &'static DoublePinyinLayout {
+        LAYOUTS.iter().find(|l| l.id == id).unwrap()
+    }
+
+    fn rules(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// The spellings `rules` give `syllable`.
+    fn spell(rules: &[String], syllable: &str) -> Vec<String> {
+        let (_, spellings) = run(rules, &[syllable.to_string()]);
+        spellings.into_iter().map(|s| s.spelling).collect()
+    }
+
+    #[test]
+    fn flypy_spellings() {
+        let algebra = layout("flypy").algebra();
+        assert_eq!(spell(&algebra, "zhuang"), ["vl"]);
+        assert_eq!(spell(&algebra, "shi"), ["ui"]);
+        assert_eq!(spell(&algebra, "a"), ["aa"]);
+        assert_eq!(spell(&algebra, "ang"), ["ah"]);
+        assert_eq!(spell(&algebra, "xue"), ["xt"]);
+        assert_eq!(spell(&algebra, "xx"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn leading_o_spellings() {
+        let algebra = layout("mspy").algebra();
+        assert_eq!(spell(&algebra, "ang"), ["oh"]);
+        assert_eq!(spell(&algebra, "ying"), ["y;"]);
+        assert_eq!(spell(&algebra, "lve"), ["lt", "lv"]);
+    }
+
+    #[test]
+    fn detects_stock_layouts() {
+        assert_eq!(detect_layout(&rules(STOCK_FLYPY)).map(|l| l.id), Some("flypy"));
+        assert_eq!(detect_layout(&rules(STOCK_MSPY)).map(|l| l.id), Some("mspy"));
+        assert_eq!(detect_layout(&rules(STOCK_SOGOU)).map(|l| l.id), Some("sogou"));
+    }
+
+    #[test]
+    fn detects_generated_layouts() {
+        for layout in LAYOUTS {
+            assert_eq!(detect_layout(&layout.algebra()).map(|l| l.id), Some(layout.id));
+        }
+        let mut with_fuzzy = layout("flypy").algebra();
+        with_fuzzy.insert(1, "derive/^n/l/".to_string());
+        assert_eq!(detect_layout(&with_fuzzy).map(|l| l.id), Some("flypy"));
+        assert!(detect_layout(&rules(&["erase/^xx$/"])).is_none());
+        assert!(detect_layout(&[]).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Spelling algebra (`speller/algebra`).
+//!
+//! Each rule is `op<sep>arg<sep>arg<sep>`, where `<sep>` is whatever
+//! character follows the operator name (usually `/`). Fuzzy pinyin is a pair
+//! of `derive` rules, one per direction; the known pairs are recognized in
+//! their common spellings, including the combined forms like
+//! `derive/^([zcs])h/$1/` that cover three pairs at once.
+//!
+//! Edits go into `<schema>.custom.yaml` as a full `speller/algebra` patch
+//! built from the effective algebra, so the base schema is never touched.
+
+pub mod double_pinyin;
+pub mod simulate;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use super::error::RimeError;
+use super::merge::compile_config;
+use super::reader::read_custom_config;
+use super::writer::write_custom_config;
+
+const ALGEBRA_KEY: &str = "speller/algebra";
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum AlgebraRule {
+    /// Replace the spelling.
+    Xform { pattern: String, replacement: String },
+    /// Add a spelling next to the original.
+    Derive { pattern: String, replacement: String },
+    /// Add a spelling usable only as an abbreviation.
+    Abbrev { pattern: String, replacement: String },
+    /// Add a spelling matched with a penalty.
+    Fuzz { pattern: String, replacement: String },
+    /// Transliterate characters one to one.
+    Xlit { from: String, to: String },
+    /// Drop spellings matching the pattern.
+    Erase { pattern: String },
+    /// Not a rule librime understands; kept as written.
+    Unknown { text: String },
+}
+
+/// A rule with the text it was parsed from.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedRule {
+    #[serde(flatten)]
+    pub rule: AlgebraRule,
+    pub raw: String,
+    /// The fuzzy pairs this rule enables, by id.
+    pub fuzzy: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FuzzyGroup {
+    Initials,
+    Finals,
+}
+
+/// A fuzzy pinyin pair: `forward` lets `to` be typed for `from`, `reverse`
+/// the other way round. The first spelling of each is the one written.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuzzyPair {
+    pub id: &'static str,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub group: FuzzyGroup,
+    pub forward: &'static [&'static str],
+    pub reverse: &'static [&'static str],
+}
+
+const ZCS_H: &str = "derive/^([zcs])h/$1/";
+const ZCS_NO_H: &str = "derive/^([zcs])([^h])/$1h$2/";
+const EI_NG: &str = "derive/([ei])ng$/$1n/";
+const EI_N: &str = "derive/([ei])n$/$1ng/";
+
+pub const FUZZY_PAIRS: &[FuzzyPair] = &[
+    FuzzyPair { id: "zh-z", from: "zh", to: "z", group: FuzzyGroup::Initials, forward: &["derive/^zh/z/", ZCS_H], reverse: &["derive/^z([^h])/zh$1/", ZCS_NO_H] },
+    FuzzyPair { id: "ch-c", from: "ch", to: "c", group: FuzzyGroup::Initials, forward: &["derive/^ch/c/", ZCS_H], reverse: &["derive/^c([^h])/ch$1/", ZCS_NO_H] },
+    FuzzyPair { id: "sh-s", from: "sh", to: "s", group: FuzzyGroup::Initials, forward: &["derive/^sh/s/", ZCS_H], reverse: &["derive/^s([^h])/sh$1/", ZCS_NO_H] },
+    FuzzyPair { id: "n-l", from: "n", to: "l", group: FuzzyGroup::Initials, forward: &["derive/^n/l/"], reverse: &["derive/^l/n/"] },
+    FuzzyPair { id: "f-h", from: "f", to: "h", group: FuzzyGroup::Initials, forward: &["derive/^f/h/"], reverse: &["derive/^h/f/"] },
+    FuzzyPair { id: "r-l", from: "r", to: "l", group: FuzzyGroup::Initials, forward: &["derive/^r/l/"], reverse: &["derive/^l/r/"] },
+    FuzzyPair { id: "g-k", from: "g", to: "k", group: FuzzyGroup::Initials, forward: &["derive/^g/k/"], reverse: &["derive/^k/g/"] },
+    FuzzyPair { id: "ang-an", from: "ang", to: "an", group: FuzzyGroup::Finals, forward: &["derive/ang$/an/"], reverse: &["derive/an$/ang/"] },
+    FuzzyPair { id: "eng-en", from: "eng", to: "en", group: FuzzyGroup::Finals, forward: &["derive/eng$/en/", EI_NG], reverse: &["derive/en$/eng/", EI_N] },
+    FuzzyPair { id: "ing-in", from: "ing", to: "in", group: FuzzyGroup::Finals, forward: &["derive/ing$/in/", EI_NG], reverse: &["derive/in$/ing/", EI_N] },
+    FuzzyPair { id: "iang-ian", from: "iang", to: "ian", group: FuzzyGroup::Finals, forward: &["derive/iang$/ian/"], reverse: &["derive/ian$/iang/"] },
+    FuzzyPair { id: "uang-uan", from: "uang", to: "uan", group: FuzzyGroup::Finals, forward: &["derive/uang$/uan/"], reverse: &["derive/uan$/uang/"] },
+    FuzzyPair { id: "ai-an", from: "ai", to: "an", group: FuzzyGroup::Finals, forward: &["derive/ai$/an/"], reverse: &["derive/an$/ai/"] },
+    FuzzyPair { id: "ong-un", from: "ong", to: "un", group: FuzzyGroup::Finals, forward: &["derive/ong$/un/"], reverse: &["derive/un$/ong/"] },
+    FuzzyPair { id: "ong-eng", from: "ong", to: "eng", group: FuzzyGroup::Finals, forward: &["derive/ong$/eng/"], reverse: &["derive/eng$/ong/"] },
+];
+
+/// Whether a known fuzzy pair is on in an algebra, per direction.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuzzyState {
+    #[serde(flatten)]
+    pub pair: FuzzyPair,
+    pub forward_enabled: bool,
+    pub reverse_enabled: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaAlgebra {
+    pub schema_id: String,
+    pub rules: Vec<ParsedRule>,
+    pub fuzzy: Vec<FuzzyState>,
+    /// Whether `<schema>.custom.yaml` patches the algebra.
+    pub patched: bool,
+    /// The id of the double pinyin layout the algebra implements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub double_pinyin: Option<&'static str>,
+}
+
+/// Parse one rule the way librime's `Calculus` does: split on the character
+/// after the operator name.
+pub fn parse_rule(text: &str) -> AlgebraRule {
+    let unknown = || AlgebraRule::Unknown { text: text.to_string() };
+    let op_len = text.find(|c: char| !c.is_ascii_lowercase()).unwrap_or(text.len());
+    let (op, rest) = text.split_at(op_len);
+    let Some(separator) = rest.chars().next() else {
+        return unknown();
+    };
+    let args: Vec<String> = rest[separator.len_utf8()..].split(separator).map(|s| s.to_string()).collect();
+    let arg = |i: usize| args.get(i).cloned();
+
+    match (op, arg(0), arg(1)) {
+        ("erase", Some(pattern), _) => AlgebraRule::Erase { pattern },
+        ("xform", Some(pattern), Some(replacement)) => AlgebraRule::Xform { pattern, replacement },
+        ("derive", Some(pattern), Some(replacement)) => AlgebraRule::Derive { pattern, replacement },
+        ("abbrev", Some(pattern), Some(replacement)) => AlgebraRule::Abbrev { pattern, replacement },
+        ("fuzz", Some(pattern), Some(replacement)) => AlgebraRule::Fuzz { pattern, replacement },
+        ("xlit", Some(from), Some(to)) => AlgebraRule::Xlit { from, to },
+        _ => unknown(),
+    }
+}
+
+/// The ids of the fuzzy pairs a rule enables in the given direction.
+fn pairs_of(raw: &str, forward: bool) -> impl Iterator<Item = &'static FuzzyPair> + '_ {
+    FUZZY_PAIRS.iter().filter(move |p| {
+        let spellings = if forward { p.forward } else { p.reverse };
+        spellings.contains(&raw)
+    })
+}
+
+pub fn parse_algebra(rules: &[String]) -> Vec<ParsedRule> {
+    rules
+        .iter()
+        .map(|raw| ParsedRule {
+            rule: parse_rule(raw),
+            raw: raw.clone(),
+            fuzzy: pairs_of(raw, true).chain(pairs_of(raw, false)).map(|p| p.id).collect(),
+        })
+        .collect()
+}
+
+pub fn detect_fuzzy(rules: &[String]) -> Vec<FuzzyState> {
+    let has = |spellings: &[&str]| rules.iter().any(|r| spellings.contains(&r.as_str()));
+    FUZZY_PAIRS
+        .iter()
+        .map(|pair| FuzzyState {
+            pair: pair.clone(),
+            forward_enabled: has(pair.forward),
+            reverse_enabled: has(pair.reverse),
+        })
+        .collect()
+}
+
+/// Whether any known fuzzy pair is on.
+pub fn has_fuzzy_pinyin(rules: &[String]) -> bool {
+    detect_fuzzy(rules).iter().any(|s| s.forward_enabled || s.reverse_enabled)
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
+/// The algebra of a compiled schema config.
+pub fn algebra_of(config: &Value) -> Vec<String> {
+    string_list(config.pointer("/speller/algebra"))
+}
+
+fn effective_algebra(schema_id: &str) -> Result<Vec<String>, RimeError> {
+    Ok(algebra_of(&compile_config(schema_id)?.config))
+}
+
+/// Whether patch key `key` sets `path` or something under it.
+fn patches_key(key: &str, path: &str) -> bool {
+    key == path || key.starts_with(&format!("{}/", path))
+}
+
+/// Whether a rule is one of the spellings of a known fuzzy pair.
+fn is_fuzzy_rule(raw: &str) -> bool {
+    pairs_of(raw, true).chain(pairs_of(raw, false)).next().is_some()
+}
+
+/// The effective algebra of a schema, parsed.
+pub fn read_algebra(schema_id: &str) -> Result<SchemaAlgebra, RimeError> {
+    let rules = effective_algebra(schema_id)?;
+    let patched = match read_custom_config(schema_id)? {
+        Value::Object(patch) => patch.keys().any(|k| patches_key(k, ALGEBRA_KEY)),
+        _ => false,
+    };
+    Ok(SchemaAlgebra {
+        schema_id: schema_id.to_string(),
+        fuzzy: detect_fuzzy(&rules),
+        double_pinyin: double_pinyin::detect_layout(&rules).map(|l| l.id),
+        rules: parse_algebra(&rules),
+        patched,
+    })
+}
+
+/// Where new fuzzy rules go: after existing ones, or else after the leading
+/// `erase` rules, so they apply to plain pinyin before any `xform` turns it
+/// into another encoding.
+fn insertion_point(rules: &[String]) -> usize {
+    match rules.iter().rposition(|r| is_fuzzy_rule(r)) {
+        Some(i) => i + 1,
+        None => rules.iter().take_while(|r| matches!(parse_rule(r), AlgebraRule::Erase { .. })).count(),
+    }
+}
+
+/// Turn one direction of a fuzzy pair on or off in `rules`. A combined rule
+/// shared with other pairs is split into their own rules when turned off.
+fn set_direction(rules: &mut Vec<String>, pair: &FuzzyPair, forward: bool, enabled: bool) {
+    let spellings = if forward { pair.forward } else { pair.reverse };
+    let present = rules.iter().any(|r| spellings.contains(&r.as_str()));
+    if enabled {
+        if !present {
+            let at = insertion_point(rules);
+            rules.insert(at, spellings[0].to_string());
+        }
+        return;
+    }
+
+    let mut i = 0;
+    while i < rules.len() {
+        if !spellings.contains(&rules[i].as_str()) {
+            i += 1;
+            continue;
+        }
+        let others: Vec<String> = pairs_of(&rules[i], forward)
+            .filter(|p| p.id != pair.id)
+            .map(|p| (if forward { p.forward } else { p.reverse })[0].to_string())
+            .filter(|r| !rules.contains(r))
+            .collect();
+        rules.remove(i);
+        for rule in others {
+            rules.insert(i, rule);
+            i += 1;
+        }
+    }
+}
+
+/// Turn fuzzy pair `id` on or off for `schema_id`, both directions unless
+/// `reverse` says otherwise, by patching `speller/algebra`.
+pub fn set_fuzzy(schema_id: &str, id: &str, enabled: bool, reverse: Option<bool>) -> Result<SchemaAlgebra, RimeError> {
+    let custom_file = format!("{}.custom.yaml", schema_id);
+    let pair = FUZZY_PAIRS
+        .iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| RimeError::invalid(Path::new(&custom_file), format!("未知的模糊音: {}", id)))?;
+
+    let mut rules = effective_algebra(schema_id)?;
+    if rules.is_empty() {
+        return Err(RimeError::invalid(Path::new(&custom_file), "该方案没有 speller/algebra, 无法设置模糊音"));
+    }
+    let original = rules.clone();
+    set_direction(&mut rules, pair, true, enabled);
+    set_direction(&mut rules, pair, false, reverse.unwrap_or(enabled));
+    if rules == original {
+        return read_algebra(schema_id);
+    }
+
+    let mut patch = match read_custom_config(schema_id)? {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    patch.retain(|key, _| !patches_key(key, ALGEBRA_KEY));
+    patch.insert(ALGEBRA_KEY.into(), Value::Array(rules.into_iter().map(Value::String).collect()));
+    write_custom_config(schema_id, &Value::Object(patch))?;
+    read_algebra(schema_id)
+}
@@ -0,0 +1,340 @@
+//! Running a spelling algebra offline.
+//!
+//! This follows librime's `Projection`: every rule is applied to every
+//! spelling in turn; `xform` and `erase` drop the spelling they change, while
+//! `derive`, `abbrev` and `fuzz` keep it next to the new one. Each spelling
+//! remembers the syllables it came from.
+//!
+//! librime uses Boost's Perl-style regexes. The `regex` crate accepts the
+//! syntax schemas use in practice; look-around and backreferences fail to
+//! compile and are reported on their rule. Replacements are rewritten from
+//! `$1` to `${1}`, since `$1h` would otherwise name a group `1h`.
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use crate::rime::dict::resolve_tables;
+use crate::rime::dict_index;
+use crate::rime::error::RimeError;
+use crate::rime::merge::compile_config;
+use super::{parse_rule, AlgebraRule};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum SpellingKind {
+    Normal,
+    Fuzzy,
+    Abbreviation,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellingSource {
+    pub syllable: String,
+    pub kind: SpellingKind,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Spelling {
+    pub spelling: String,
+    pub sources: Vec<SpellingSource>,
+}
+
+/// What one rule did to the set of spellings.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleEffect {
+    pub raw: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Why the rule was skipped. librime rejects the whole algebra instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AlgebraSimulation {
+    pub schema_id: String,
+    /// The dictionary the syllables came from; absent for sample input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<String>,
+    pub syllables: Vec<String>,
+    pub rules: Vec<RuleEffect>,
+    pub spellings: Vec<Spelling>,
+}
+
+/// Spelling -> syllable -> kind.
+type Script = BTreeMap<String, BTreeMap<String, SpellingKind>>;
+
+enum Calculation {
+    Replace { pattern: Regex, replacement: String, keep: bool, kind: SpellingKind },
+    Erase { pattern: Regex },
+    Transliterate { map: BTreeMap<char, char> },
+}
+
+/// Rewrite a Boost/Perl replacement into `regex` syntax: `$1` becomes
+/// `${1}`, `$&` the whole match, and `\x` a literal `x`.
+pub fn translate_replacement(replacement: &str) -> String {
+    let mut out = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => match chars.peek().copied() {
+                Some('&') => {
+                    chars.next();
+                    out.push_str("${0}");
+                }
+                Some('$') => {
+                    chars.next();
+                    out.push_str("$$");
+                }
+                Some('{') => out.push('$'),
+                Some(d) if d.is_ascii_digit() => {
+                    let mut group = String::new();
+                    while let Some(d) = chars.peek().copied().filter(|d| d.is_ascii_digit()) {
+                        group.push(d);
+                        chars.next();
+                    }
+                    out.push_str(&format!("${{{}}}", group));
+                }
+                _ => out.push_str("$$"),
+            },
+            '\\' => match chars.next() {
+                Some('$') => out.push_str("$$"),
+                Some(escaped) => out.push(escaped),
+                None => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+fn calculation(rule: &AlgebraRule) -> Result<Calculation, String> {
+    let replace = |pattern: &str, replacement: &str, keep, kind| {
+        Ok(Calculation::Replace {
+            pattern: compile(pattern)?,
+            replacement: translate_replacement(replacement),
+            keep,
+            kind,
+        })
+    };
+    match rule {
+        AlgebraRule::Xform { pattern, replacement } => replace(pattern, replacement, false, SpellingKind::Normal),
+        AlgebraRule::Derive { pattern, replacement } => replace(pattern, replacement, true, SpellingKind::Normal),
+        AlgebraRule::Abbrev { pattern, replacement } => replace(pattern, replacement, true, SpellingKind::Abbreviation),
+        AlgebraRule::Fuzz { pattern, replacement } => replace(pattern, replacement, true, SpellingKind::Fuzzy),
+        // `erase` only removes spellings the pattern matches as a whole.
+        AlgebraRule::Erase { pattern } => Ok(Calculation::Erase { pattern: compile(&format!("^(?:{})$", pattern))? }),
+        AlgebraRule::Xlit { from, to } => {
+            let (from, to): (Vec<char>, Vec<char>) = (from.chars().collect(), to.chars().collect());
+            if from.len() != to.len() {
+                return Err("xlit 两侧的字符数不同".to_string());
+            }
+            Ok(Calculation::Transliterate { map: from.into_iter().zip(to).collect() })
+        }
+        AlgebraRule::Unknown { .. } => Err("无法识别的规则".to_string()),
+    }
+}
+
+fn merge(script: &mut Script, spelling: String, sources: &BTreeMap<String, SpellingKind>, kind: SpellingKind) {
+    let entry = script.entry(spelling).or_default();
+    for (syllable, source_kind) in sources {
+        let kind = kind.max(*source_kind);
+        entry
+            .entry(syllable.clone())
+            .and_modify(|k| *k = (*k).min(kind))
+            .or_insert(kind);
+    }
+}
+
+fn apply(calculation: &Calculation, script: &Script) -> Script {
+    let mut next = Script::new();
+    for (spelling, sources) in script {
+        match calculation {
+            Calculation::Replace { pattern, replacement, keep, kind } => {
+                let result = pattern.replace_all(spelling, replacement.as_str());
+                let changed = result != spelling.as_str();
+                if !changed || *keep {
+                    merge(&mut next, spelling.clone(), sources, SpellingKind::Normal);
+                }
+                // A spelling rewritten to nothing is dropped.
+                if changed && !result.is_empty() {
+                    merge(&mut next, result.into_owned(), sources, *kind);
+                }
+            }
+            Calculation::Erase { pattern } => {
+                if !pattern.is_match(spelling) {
+                    merge(&mut next, spelling.clone(), sources, SpellingKind::Normal);
+                }
+            }
+            Calculation::Transliterate { map } => {
+                let result: String = spelling.chars().map(|c| *map.get(&c).unwrap_or(&c)).collect();
+                merge(&mut next, result, sources, SpellingKind::Normal);
+            }
+        }
+    }
+    next
+}
+
+/// Run `rules` over `syllables`.
+pub fn run(rules: &[String], syllables: &[String]) -> (Vec<RuleEffect>, Vec<Spelling>) {
+    let mut script: Script = syllables
+        .iter()
+        .map(|s| (s.clone(), BTreeMap::from([(s.clone(), SpellingKind::Normal)])))
+        .collect();
+    let mut effects = Vec::new();
+
+    for raw in rules {
+        let calculation = match calculation(&parse_rule(raw)) {
+            Ok(c) => c,
+            Err(error) => {
+                effects.push(RuleEffect { raw: raw.clone(), added: vec![], removed: vec![], error: Some(error) });
+                continue;
+            }
+        };
+        let next = apply(&calculation, &script);
+        effects.push(RuleEffect {
+            raw: raw.clone(),
+            added: next.keys().filter(|k| !script.contains_key(*k)).cloned().collect(),
+            removed: script.keys().filter(|k| !next.contains_key(*k)).cloned().collect(),
+            error: None,
+        });
+        script = next;
+    }
+
+    let spellings = script
+        .into_iter()
+        .map(|(spelling, sources)| Spelling {
+            spelling,
+            sources: sources.into_iter().map(|(syllable, kind)| SpellingSource { syllable, kind }).collect(),
+        })
+        .collect();
+    (effects, spellings)
+}
+
+/// The syllables of a dictionary and the tables it imports: every
+/// space-separated part of every code.
+pub fn dictionary_syllables(name: &str) -> Result<Vec<String>, RimeError> {
+    let mut syllables = BTreeSet::new();
+    for table in resolve_tables(name)? {
+        let index = dict_index::load(&table.id)?;
+        for code in index.codes() {
+            syllables.extend(code.split(' ').filter(|s| !s.is_empty()).map(str::to_string));
+        }
+    }
+    Ok(syllables.into_iter().collect())
+}
+
+/// Simulate the algebra of `schema_id`, or `rules` in its place, over the
+/// syllables of its `translator/dictionary`, or over `sample` if given.
+pub fn simulate(
+    schema_id: &str,
+    rules: Option<Vec<String>>,
+    sample: Option<Vec<String>>,
+) -> Result<AlgebraSimulation, RimeError> {
+    let config = compile_config(schema_id)?.config;
+    let rules = match rules {
+        Some(rules) => rules,
+        None => super::string_list(config.pointer("/speller/algebra")),
+    };
+
+    let (dictionary, syllables) = match sample {
+        Some(sample) => {
+            let set: BTreeSet<String> = sample.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+            (None, set.into_iter().collect())
+        }
+        None => {
+            let dictionary = config
+                .pointer("/translator/dictionary")
+                .and_then(|v| v.as_str())
+                .filter(|d| !d.is_empty())
+                .ok_or_else(|| {
+                    RimeError::invalid(Path::new(&format!("{}.schema.yaml", schema_id)), "方案没有 translator/dictionary, 请提供音节")
+                })?;
+            (Some(dictionary.to_string()), dictionary_syllables(dictionary)?)
+        }
+    };
+
+    let (rules, spellings) = run(&rules, &syllables);
+    Ok(AlgebraSimulation {
+        schema_id: schema_id.to_string(),
+        dictionary,
+        syllables,
+        rules,
+        spellings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn spellings(rule_list: &[&str], syllables: &[&str]) -> Vec<String> {
+        let (_, spellings) = run(&rules(rule_list), &rules(syllables));
+        spellings.into_iter().map(|s| s.spelling).collect()
+    }
+
+    #[test]
+    fn replacements_use_braced_groups() {
+        assert_eq!(translate_replacement("$1h"), "${1}h");
+        assert_eq!(translate_replacement("$1$2"), "${1}${2}");
+        assert_eq!(translate_replacement("<$&>"), "<${0}>");
+        assert_eq!(translate_replacement(r"\$x"), "$$x");
+        assert_eq!(spellings(&["derive/^([jqxy])u$/$1v/"], &["ju", "lu"]), ["ju", "jv", "lu"]);
+    }
+
+    #[test]
+    fn xform_replaces_the_spelling() {
+        assert_eq!(spellings(&["xform/^([nl])ue$/$1ve/"], &["lue", "nue", "xue"]), ["lve", "nve", "xue"]);
+        // A spelling rewritten to nothing is dropped.
+        assert_eq!(spellings(&["xform/^a$//"], &["a", "b"]), ["b"]);
+    }
+
+    #[test]
+    fn derive_keeps_the_original() {
+        let (effects, spellings) = run(&rules(&["derive/^([zcs])h/$1/"]), &rules(&["chi", "zhi", "ci"]));
+        let names: Vec<&str> = spellings.iter().map(|s| s.spelling.as_str()).collect();
+        assert_eq!(names, ["chi", "ci", "zhi", "zi"]);
+        assert_eq!(effects[0].added, ["zi"]);
+        assert!(effects[0].removed.is_empty());
+        // `ci` is both a syllable of its own and derived from `chi`.
+        let sources: Vec<&str> = spellings[1].sources.iter().map(|s| s.syllable.as_str()).collect();
+        assert_eq!(sources, ["chi", "ci"]);
+    }
+
+    #[test]
+    fn erase_matches_whole_spellings() {
+        let (effects, _) = run(&rules(&["erase/^xx$/", "erase/a/"]), &rules(&["xx", "xxa", "a"]));
+        assert_eq!(effects[0].removed, ["xx"]);
+        assert_eq!(effects[1].removed, ["a"]);
+    }
+
+    #[test]
+    fn xlit_maps_characters() {
+        assert_eq!(spellings(&["xlit/abc/xyz/"], &["cab", "d"]), ["d", "zxy"]);
+        assert_eq!(spellings(&["xform/^sh/Ⓤ/", "xlit/Ⓤ/u/"], &["shi"]), ["ui"]);
+        let (effects, _) = run(&rules(&["xlit/ab/x/"]), &rules(&["a"]));
+        assert!(effects[0].error.is_some());
+    }
+
+    #[test]
+    fn fuzz_and_abbrev_mark_their_spellings() {
+        let (_, spellings) = run(&rules(&["abbrev/^([a-z]).+$/$1/", "fuzz/^zh/z/"]), &rules(&["zhong"]));
+        let kind = |name: &str| spellings.iter().find(|s| s.spelling == name).map(|s| s.sources[0].kind);
+        assert_eq!(kind("zhong"), Some(SpellingKind::Normal));
+        assert_eq!(kind("z"), Some(SpellingKind::Abbreviation));
+        assert_eq!(kind("zong"), Some(SpellingKind::Fuzzy));
+    }
+}
@@ -1,66 +1,433 @@
-use std::process::Command;
-
-/// Trigger Rime deploy (re-read config files).
-pub fn trigger_deploy() -> (bool, String) {
-    if cfg!(target_os = "macos") {
-        deploy_macos()
-    } else if cfg!(target_os = "linux") {
-        deploy_linux()
-    } else if cfg!(target_os = "windows") {
-        deploy_windows()
-    } else {
-        (false, "不支持的平台".into())
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use super::frontend::detect_frontend;
+use super::offline::{self, BuildDirs};
+use super::paths::get_rime_config_dir;
+use super::schema_list::{enabled_schemas, schema_ids_of};
+use super::yaml_utils::read_yaml_file;
+
+/// How long to wait for the frontend to finish compiling after a deploy request.
+const BUILD_TIMEOUT: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeployReport {
+    pub success: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frontend: Option<String>,
+    pub duration_ms: u64,
+    pub schemas: Vec<SchemaBuildStatus>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaBuildStatus {
+    pub schema_id: String,
+    pub success: bool,
+    pub artifacts: Vec<BuildArtifact>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildArtifact {
+    pub file: String,
+    /// Written by this deployment (as opposed to already up to date).
+    pub updated: bool,
+}
+
+impl DeployReport {
+    fn failed(frontend: Option<String>, started: Instant, message: String) -> Self {
+        DeployReport {
+            success: false,
+            message,
+            frontend,
+            duration_ms: started.elapsed().as_millis() as u64,
+            schemas: vec![],
+            warnings: vec![],
+            errors: vec![],
+        }
     }
 }
 
-fn deploy_macos() -> (bool, String) {
-    let result = Command::new("/Library/Input Methods/Squirrel.app/Contents/MacOS/Squirrel")
-        .arg("--reload")
-        .output();
-
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                (true, "已通知鼠须管重新部署".into())
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                (false, format!("部署失败: {}", stderr))
-            }
+/// Trigger Rime deploy (re-read config files) through the detected frontend,
+/// then verify the outcome from `build/` timestamps and the Rime log files.
+/// Only the frontend's own user directory can be deployed this way; other
+/// directories go through `deploy_offline`.
+///
+/// With `sync`, user data is synced through `sync_dir` once the build is done;
+/// a failed sync is reported as a warning, not as a failed deploy.
+pub fn trigger_deploy(sync: bool) -> DeployReport {
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+
+    let frontend = match detect_frontend() {
+        Some(f) => f,
+        None => return DeployReport::failed(None, started, "部署失败: 未找到支持的输入框架".into()),
+    };
+    let frontend_name = Some(frontend.name().to_string());
+
+    // Frontends rebuild their own user directory whatever dir is active, so
+    // deploying a profile elsewhere would only time out waiting for its build/.
+    let config_dir = get_rime_config_dir();
+    let Some(frontend_dir) = frontend.config_dir() else {
+        return DeployReport::failed(frontend_name, started, "部署失败: 未找到输入法, 请使用离线部署".into());
+    };
+    if !same_dir(&frontend_dir, &config_dir) {
+        return DeployReport::failed(
+            frontend_name,
+            started,
+            format!(
+                "部署失败: {} 只会部署 {}, 当前目录 {} 不会被编译; 请切换回该目录或使用离线部署",
+                frontend.name(),
+                frontend_dir.display(),
+                config_dir.display()
+            ),
+        );
+    }
+    let mut warnings = Vec::new();
+    let schema_ids = match enabled_schema_ids() {
+        Ok(ids) => ids,
+        Err(e) => {
+            warnings.push(format!("无法读取 schema_list: {}", e));
+            vec![]
+        }
+    };
+
+    let logs = LogCursor::snapshot(&frontend.log_dirs());
+
+    let message = match frontend.deploy(&config_dir) {
+        Ok(message) => message,
+        Err(e) => return DeployReport::failed(frontend_name, started, format!("部署失败: {}", e)),
+    };
+
+    let target = BuildTarget {
+        build_dir: config_dir.join("build"),
+        config_dir: config_dir.clone(),
+    };
+    let mut report = target.verify(frontend_name, started, started_at, logs, message, schema_ids, warnings);
+
+    if sync {
+        match frontend.sync(&config_dir) {
+            Ok(message) => report.message = format!("{}; {}", report.message, message),
+            Err(e) => report.warnings.push(format!("同步失败: {}", e)),
         }
-        Err(e) => (false, format!("部署失败: {}", e)),
+        report.duration_ms = started.elapsed().as_millis() as u64;
     }
+    report
 }
 
-fn deploy_linux() -> (bool, String) {
-    // Try fcitx5 first
-    if let Ok(output) = Command::new("fcitx5-remote").arg("-r").output() {
-        if output.status.success() {
-            return (true, "Fcitx5 Rime 已重新部署".into());
+/// Compile a user directory with librime's deployer, without any input method
+/// running. Defaults: the active config dir, the system `rime-data` as shared
+/// dir and `<user_dir>/build` as staging dir.
+pub fn deploy_offline(
+    user_dir: Option<PathBuf>,
+    shared_dir: Option<PathBuf>,
+    staging_dir: Option<PathBuf>,
+) -> DeployReport {
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+    let frontend_name = Some("rime_deployer".to_string());
+
+    if !offline::is_available() {
+        return DeployReport::failed(frontend_name, started, "部署失败: 未找到 rime_deployer".into());
+    }
+
+    let dirs = BuildDirs::resolve(
+        user_dir.unwrap_or_else(get_rime_config_dir),
+        shared_dir,
+        staging_dir,
+    );
+    let logs = LogCursor::snapshot(&[std::env::temp_dir()]);
+
+    let message = match offline::build(&dirs) {
+        Ok(message) => message,
+        Err(e) => return DeployReport::failed(frontend_name, started, format!("部署失败: {}", e)),
+    };
+
+    // The build is synchronous, so the compiled default.yaml already holds the schema list.
+    let mut warnings = Vec::new();
+    let schema_ids = match read_yaml_file(&dirs.staging_dir.join("default.yaml"), false) {
+        Ok(Some(compiled)) => schema_ids_of(&compiled),
+        Ok(None) => {
+            warnings.push("未生成 default.yaml".into());
+            vec![]
+        }
+        Err(e) => {
+            warnings.push(format!("无法读取 schema_list: {}", e));
+            vec![]
+        }
+    };
+
+    let target = BuildTarget {
+        config_dir: dirs.user_dir,
+        build_dir: dirs.staging_dir,
+    };
+    target.verify(frontend_name, started, started_at, logs, message, schema_ids, warnings)
+}
+
+/// Where sources are read from and where compiled output is expected.
+struct BuildTarget {
+    config_dir: PathBuf,
+    build_dir: PathBuf,
+}
+
+impl BuildTarget {
+    /// Wait for the build to catch up with its sources, then assemble the report.
+    #[allow(clippy::too_many_arguments)]
+    fn verify(
+        &self,
+        frontend: Option<String>,
+        started: Instant,
+        started_at: SystemTime,
+        logs: LogCursor,
+        message: String,
+        schema_ids: Vec<String>,
+        mut warnings: Vec<String>,
+    ) -> DeployReport {
+        let deadline = Instant::now() + BUILD_TIMEOUT;
+        let completed = loop {
+            if self.is_current("default", "default.yaml")
+                && schema_ids.iter().all(|id| self.schema_is_current(id))
+            {
+                break true;
+            }
+            if Instant::now() >= deadline {
+                break false;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        let (log_warnings, log_errors) = logs.read_new();
+
+        let mut claimed: HashSet<usize> = HashSet::new();
+        let schemas: Vec<SchemaBuildStatus> = schema_ids
+            .iter()
+            .map(|id| self.schema_status(id, started_at, &log_warnings, &log_errors, &mut claimed))
+            .collect();
+
+        if !self.is_current("default", "default.yaml") {
+            warnings.push("build/default.yaml 未更新".into());
+        }
+        warnings.extend(log_warnings);
+        let errors: Vec<String> = log_errors
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !claimed.contains(i))
+            .map(|(_, e)| e)
+            .collect();
+
+        let failed = schemas.iter().filter(|s| !s.success).count();
+        let error_count = errors.len() + schemas.iter().map(|s| s.errors.len()).sum::<usize>();
+        let success = completed && failed == 0 && error_count == 0;
+
+        let message = if !completed {
+            format!("{}, 但等待超时: {} 个方案未完成编译", message, failed)
+        } else if error_count > 0 {
+            format!("{}, 编译出现 {} 个错误", message, error_count)
+        } else {
+            message
+        };
+
+        DeployReport {
+            success,
+            message,
+            frontend,
+            duration_ms: started.elapsed().as_millis() as u64,
+            schemas,
+            warnings,
+            errors,
         }
     }
-    // Fallback to ibus
-    if let Ok(output) = Command::new("ibus").arg("write-cache").output() {
-        if output.status.success() {
-            return (true, "IBus Rime 已重新部署".into());
+
+    /// Whether `<build_dir>/<target>` exists and is no older than the files it's compiled from.
+    fn is_current(&self, basename: &str, target: &str) -> bool {
+        let built = match modified(&self.build_dir.join(target)) {
+            Some(t) => t,
+            None => return false,
+        };
+        [target.to_string(), format!("{}.custom.yaml", basename)]
+            .iter()
+            .filter_map(|source| modified(&self.config_dir.join(source)))
+            .all(|t| t <= built)
+    }
+
+    fn schema_is_current(&self, schema_id: &str) -> bool {
+        self.is_current(schema_id, &format!("{}.schema.yaml", schema_id))
+    }
+
+    fn schema_status(
+        &self,
+        schema_id: &str,
+        started_at: SystemTime,
+        log_warnings: &[String],
+        log_errors: &[String],
+        claimed: &mut HashSet<usize>,
+    ) -> SchemaBuildStatus {
+        let compiled = self.build_dir.join(format!("{}.schema.yaml", schema_id));
+
+        // The compiled schema tells us which dictionary it uses.
+        let dictionary = read_yaml_file(&compiled, false)
+            .ok()
+            .flatten()
+            .and_then(|v| {
+                v.pointer("/translator/dictionary")
+                    .and_then(|d| d.as_str())
+                    .map(|d| d.to_string())
+            })
+            .filter(|d| !d.is_empty());
+
+        let mut expected = vec![format!("{}.schema.yaml", schema_id)];
+        if let Some(dict) = &dictionary {
+            expected.push(format!("{}.prism.bin", schema_id));
+            expected.push(format!("{}.table.bin", dict));
+        }
+
+        let mut errors = Vec::new();
+        let mut artifacts = Vec::new();
+        for file in &expected {
+            match modified(&self.build_dir.join(file)) {
+                Some(t) => artifacts.push(BuildArtifact {
+                    file: file.clone(),
+                    updated: t >= started_at,
+                }),
+                None => errors.push(format!("缺少 build/{}", file)),
+            }
+        }
+        if let Some(dict) = &dictionary {
+            let reverse = format!("{}.reverse.bin", dict);
+            if let Some(t) = modified(&self.build_dir.join(&reverse)) {
+                artifacts.push(BuildArtifact { file: reverse, updated: t >= started_at });
+            }
+        }
+        if !self.schema_is_current(schema_id) {
+            errors.push(format!("build/{}.schema.yaml 早于源文件", schema_id));
+        }
+
+        let mentions = |line: &str| {
+            line.contains(schema_id) || dictionary.as_deref().map(|d| line.contains(d)).unwrap_or(false)
+        };
+        for (i, line) in log_errors.iter().enumerate() {
+            if mentions(line) {
+                claimed.insert(i);
+                errors.push(line.clone());
+            }
+        }
+        let warnings: Vec<String> = log_warnings.iter().filter(|l| mentions(l)).cloned().collect();
+
+        SchemaBuildStatus {
+            schema_id: schema_id.to_string(),
+            success: errors.is_empty(),
+            artifacts,
+            warnings,
+            errors,
         }
     }
-    (false, "部署失败: 未找到支持的输入框架".into())
 }
 
-fn deploy_windows() -> (bool, String) {
-    let result = Command::new("C:\\Program Files (x86)\\Rime\\weasel-0.15.0\\WeaselDeployer.exe")
-        .arg("/deploy")
-        .output();
-
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                (true, "小狼毫已重新部署".into())
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                (false, format!("部署失败: {}", stderr))
+fn enabled_schema_ids() -> Result<Vec<String>, String> {
+    enabled_schemas().map_err(|e| e.to_string())
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Remembers how far each Rime log file had been written before a deploy,
+/// so only lines produced by this deployment are read afterwards.
+struct LogCursor {
+    dirs: Vec<PathBuf>,
+    offsets: HashMap<PathBuf, u64>,
+}
+
+impl LogCursor {
+    fn snapshot(dirs: &[PathBuf]) -> Self {
+        let offsets = log_files(dirs)
+            .into_iter()
+            .filter_map(|p| std::fs::metadata(&p).ok().map(|m| (p, m.len())))
+            .collect();
+        LogCursor { dirs: dirs.to_vec(), offsets }
+    }
+
+    /// New warning and error lines, de-duplicated (glog repeats errors in every
+    /// lower-severity file).
+    fn read_new(&self) -> (Vec<String>, Vec<String>) {
+        let mut seen = HashSet::new();
+        let mut warnings = Vec::new();
+        let mut errors = Vec::new();
+
+        for path in log_files(&self.dirs) {
+            let offset = self.offsets.get(&path).copied().unwrap_or(0);
+            let mut file = match std::fs::File::open(&path) {
+                Ok(f) => f,
+                Err(_) => continue,
+            };
+            if file.seek(SeekFrom::Start(offset)).is_err() {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            if file.read_to_end(&mut bytes).is_err() {
+                continue;
+            }
+            for line in String::from_utf8_lossy(&bytes).lines() {
+                let (level, message) = match parse_log_line(line) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                if !seen.insert(line.to_string()) {
+                    continue;
+                }
+                match level {
+                    'W' => warnings.push(message.to_string()),
+                    'E' | 'F' => errors.push(message.to_string()),
+                    _ => {}
+                }
             }
         }
-        Err(e) => (false, format!("部署失败: {}", e)),
+
+        (warnings, errors)
+    }
+}
+
+/// Regular glog files written by librime (`rime.<app>.<host>.<user>.log.<LEVEL>.<time>.<pid>`).
+/// The `rime.<app>.INFO` style symlinks are skipped so nothing is read twice.
+fn log_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.starts_with("rime") && name.contains(".log.")
+        })
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|e| e.path())
+        .collect()
+}
+
+/// Split a glog line (`E20240101 12:00:00.000000 1234 file.cc:42] message`)
+/// into its severity and message.
+fn parse_log_line(line: &str) -> Option<(char, &str)> {
+    let level = line.chars().next()?;
+    if !matches!(level, 'I' | 'W' | 'E' | 'F') {
+        return None;
+    }
+    if !line[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
     }
+    line.split_once("] ").map(|(_, message)| (level, message))
 }
@@ -0,0 +1,445 @@
+//! Rime dictionaries (`*.dict.yaml`).
+//!
+//! A dictionary is a YAML header closed by `...`, followed by a tab-separated
+//! body whose fields are given by the header's `columns` (default `text`,
+//! `code`, `weight`). `import_tables` pulls in the entries of other
+//! dictionaries, which may live in subfolders (`cn_dicts/base`).
+//!
+//! Edits rewrite single lines of the body, so comments and the header stay
+//! as they are. Entries are addressed by line number together with their
+//! previous content, and an edit is refused if the line no longer matches.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+use super::dict_index::{self, DictQuery};
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::git;
+use super::history::snapshot;
+use super::paths::{get_rime_config_dir, get_shared_data_dir, resolve_config_path, Layer};
+use super::yaml_utils::parse_yaml;
+
+pub const DICT_SUFFIX: &str = ".dict.yaml";
+pub const HEADER_END: &str = "...";
+pub const DEFAULT_COLUMNS: [&str; 3] = ["text", "code", "weight"];
+/// Folders of the user dir that never hold source dictionaries.
+const SKIPPED_DIRS: [&str; 5] = ["build", "sync", ".rime-app", ".git", "node_modules"];
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DictInfo {
+    /// What `import_tables` and `translator/dictionary` refer to it by: the
+    /// file path without `.dict.yaml`, e.g. `cn_dicts/base`.
+    pub id: String,
+    /// The header's `name`.
+    pub name: String,
+    pub layer: Layer,
+    pub version: String,
+    pub sort: String,
+    pub columns: Vec<String>,
+    pub import_tables: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DictEntry {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Kept as written: Rime also accepts percentages such as `10%`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stem: Option<String>,
+}
+
+/// An entry found by `search`, with the table and line it came from.
+#[derive(Debug, Serialize, Clone)]
+pub struct DictHit {
+    pub table: String,
+    pub line: usize,
+    #[serde(flatten)]
+    pub entry: DictEntry,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DictPage {
+    pub total: usize,
+    pub entries: Vec<DictHit>,
+}
+
+/// A dictionary file held as lines (with their endings) for editing.
+struct DictFile {
+    name: String,
+    path: PathBuf,
+    layer: Layer,
+    columns: Vec<String>,
+    lines: Vec<String>,
+    /// Index of the first line after the header.
+    body_start: usize,
+}
+
+pub fn dict_filename(name: &str) -> Result<String, RimeError> {
+    let relative = Path::new(name);
+    let valid = !name.is_empty() && relative.components().all(|c| matches!(c, Component::Normal(_)));
+    if !valid {
+        return Err(RimeError::invalid(relative, "无效的词典名"));
+    }
+    Ok(format!("{}{}", name, DICT_SUFFIX))
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
+fn info_from_header(id: &str, layer: Layer, header: &Value) -> DictInfo {
+    let text = |key: &str| {
+        header
+            .get(key)
+            .map(|v| v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.to_string()))
+            .unwrap_or_default()
+    };
+    let columns = string_list(header.get("columns"));
+    DictInfo {
+        id: id.to_string(),
+        name: header
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or(id)
+            .to_string(),
+        layer,
+        version: text("version"),
+        sort: text("sort"),
+        columns: if columns.is_empty() {
+            DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect()
+        } else {
+            columns
+        },
+        import_tables: string_list(header.get("import_tables")),
+    }
+}
+
+pub fn parse_header(path: &Path, header: &str) -> Result<Value, RimeError> {
+    let value = parse_yaml(header).map_err(|e| RimeError::parse(path, e))?;
+    if value.is_object() {
+        Ok(value)
+    } else {
+        Err(RimeError::invalid(path, "词典头部必须是映射"))
+    }
+}
+
+/// Read just the header, without loading the body.
+fn read_header(path: &Path) -> Result<Value, RimeError> {
+    let file = std::fs::File::open(path).map_err(|e| RimeError::io(path, e))?;
+    let mut header = String::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| RimeError::io(path, e))?;
+        if line.trim_end() == HEADER_END {
+            return parse_header(path, &header);
+        }
+        header.push_str(&line);
+        header.push('\n');
+    }
+    Err(RimeError::invalid(path, "缺少头部结束标记 `...`"))
+}
+
+/// Parse a body line into an entry; comments and blank lines give `None`.
+fn parse_entry(line: &str, columns: &[String]) -> Option<DictEntry> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut entry = DictEntry {
+        text: String::new(),
+        code: None,
+        weight: None,
+        stem: None,
+    };
+    for (column, field) in columns.iter().zip(line.split('\t')) {
+        let field = Some(field.to_string()).filter(|f| !f.is_empty());
+        match column.as_str() {
+            "text" => entry.text = field.unwrap_or_default(),
+            "code" => entry.code = field,
+            "weight" => entry.weight = field,
+            "stem" => entry.stem = field,
+            _ => {}
+        }
+    }
+    Some(entry).filter(|e| !e.text.is_empty())
+}
+
+/// Format an entry for the given columns, dropping trailing empty fields.
+fn format_entry(entry: &DictEntry, columns: &[String]) -> String {
+    let mut fields: Vec<&str> = columns
+        .iter()
+        .map(|column| match column.as_str() {
+            "text" => entry.text.as_str(),
+            "code" => entry.code.as_deref().unwrap_or(""),
+            "weight" => entry.weight.as_deref().unwrap_or(""),
+            "stem" => entry.stem.as_deref().unwrap_or(""),
+            _ => "",
+        })
+        .collect();
+    while fields.last() == Some(&"") {
+        fields.pop();
+    }
+    fields.join("\t")
+}
+
+fn validate_entry(path: &Path, entry: &DictEntry, columns: &[String]) -> Result<(), RimeError> {
+    let fields = [Some(&entry.text), entry.code.as_ref(), entry.weight.as_ref(), entry.stem.as_ref()];
+    if entry.text.trim().is_empty() {
+        return Err(RimeError::invalid(path, "词条文字不能为空"));
+    }
+    if fields.iter().flatten().any(|f| f.contains(['\t', '\n', '\r'])) {
+        return Err(RimeError::invalid(path, "词条不能包含制表符或换行"));
+    }
+    if entry.text.starts_with('#') {
+        return Err(RimeError::invalid(path, "词条不能以 # 开头"));
+    }
+    let has = |column: &str| columns.iter().any(|c| c == column);
+    if (entry.code.is_some() && !has("code"))
+        || (entry.weight.is_some() && !has("weight"))
+        || (entry.stem.is_some() && !has("stem"))
+    {
+        return Err(RimeError::invalid(path, "词典的 columns 中没有对应的列"));
+    }
+    Ok(())
+}
+
+impl DictFile {
+    fn load(name: &str) -> Result<Self, RimeError> {
+        let filename = dict_filename(name)?;
+        let (path, layer) = resolve_config_path(&filename)
+            .ok_or_else(|| RimeError::invalid(Path::new(&filename), "未找到词典"))?;
+        let content = std::fs::read_to_string(&path).map_err(|e| RimeError::io(&path, e))?;
+        let lines: Vec<String> = content.split_inclusive('\n').map(|l| l.to_string()).collect();
+
+        let end = lines
+            .iter()
+            .position(|l| l.trim_end() == HEADER_END)
+            .ok_or_else(|| RimeError::invalid(&path, "缺少头部结束标记 `...`"))?;
+        let header = parse_header(&path, &lines[..end].concat())?;
+        let info = info_from_header(name, layer, &header);
+
+        Ok(DictFile {
+            name: name.to_string(),
+            path,
+            layer,
+            columns: info.columns,
+            lines,
+            body_start: end + 1,
+        })
+    }
+
+    fn line_ending(&self) -> &'static str {
+        if self.lines.first().map(|l| l.ends_with("\r\n")).unwrap_or(false) {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Index of `line` (1-based) if it still holds `expected`.
+    fn locate(&self, line: usize, expected: &DictEntry) -> Result<usize, RimeError> {
+        let index = line.checked_sub(1).filter(|i| *i >= self.body_start && *i < self.lines.len());
+        match index {
+            Some(i) if parse_entry(&self.lines[i], &self.columns).as_ref() == Some(expected) => Ok(i),
+            _ => Err(RimeError::invalid(&self.path, format!("第 {} 行已被修改, 请重新加载", line))),
+        }
+    }
+
+    /// Write back. Shared dictionaries are copied into the user dir, which
+    /// Rime reads first.
+    fn save(&mut self, message: &str) -> Result<(), RimeError> {
+        if self.layer == Layer::Shared {
+            self.path = get_rime_config_dir().join(dict_filename(&self.name)?);
+            self.layer = Layer::User;
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| RimeError::io(dir, e))?;
+        }
+        snapshot(&self.path)?;
+        write_atomic(&self.path, self.lines.concat())?;
+        dict_index::invalidate(&self.path);
+        git::record(&[&self.path], message);
+        Ok(())
+    }
+}
+
+fn collect_dicts(root: &Path, dir: &Path, depth: usize, layer: Layer, seen: &mut HashSet<String>, out: &mut Vec<(String, PathBuf, Layer)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            if depth > 0 && !SKIPPED_DIRS.contains(&file_name.as_str()) && !file_name.ends_with(".userdb") {
+                collect_dicts(root, &path, depth - 1, layer, seen, out);
+            }
+        } else if let Some(relative) = path
+            .strip_prefix(root)
+            .ok()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .and_then(|p| p.strip_suffix(DICT_SUFFIX).map(|n| n.to_string()))
+        {
+            if seen.insert(relative.clone()) {
+                out.push((relative, path, layer));
+            }
+        }
+    }
+}
+
+/// All dictionaries in the user dir and the shared data dir (including one
+/// level of subfolders), user files shadowing shared ones.
+///
+/// Dictionaries whose header doesn't parse are returned as errors.
+pub fn list_dictionaries() -> (Vec<DictInfo>, Vec<RimeError>) {
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    let user = get_rime_config_dir();
+    collect_dicts(&user, &user, 1, Layer::User, &mut seen, &mut found);
+    if let Some(shared) = get_shared_data_dir() {
+        collect_dicts(&shared, &shared, 1, Layer::Shared, &mut seen, &mut found);
+    }
+
+    let mut dicts = Vec::new();
+    let mut errors = Vec::new();
+    for (name, path, layer) in found {
+        match read_header(&path) {
+            Ok(header) => dicts.push(info_from_header(&name, layer, &header)),
+            Err(e) => errors.push(e),
+        }
+    }
+    (dicts, errors)
+}
+
+/// A dictionary and everything it imports, depth first, each table once.
+pub fn resolve_tables(name: &str) -> Result<Vec<DictInfo>, RimeError> {
+    let mut tables = Vec::new();
+    let mut visited = HashSet::new();
+    resolve_into(name, &mut visited, &mut tables)?;
+    Ok(tables)
+}
+
+fn resolve_into(name: &str, visited: &mut HashSet<String>, out: &mut Vec<DictInfo>) -> Result<(), RimeError> {
+    if !visited.insert(name.to_string()) {
+        return Ok(());
+    }
+    let filename = dict_filename(name)?;
+    let (path, layer) = resolve_config_path(&filename)
+        .ok_or_else(|| RimeError::invalid(Path::new(&filename), "未找到词典"))?;
+    let info = info_from_header(name, layer, &read_header(&path)?);
+    let imports = info.import_tables.clone();
+    out.push(info);
+    for import in imports {
+        resolve_into(&import, visited, out)?;
+    }
+    Ok(())
+}
+
+/// A table missing from a dictionary's import chain.
+#[derive(Debug, Clone)]
+pub struct MissingTable {
+    pub name: String,
+    /// The dictionary importing it; `None` for the dictionary asked about.
+    pub imported_by: Option<String>,
+}
+
+/// Walk the import chain of `name` like `resolve_tables`, but collect every
+/// table that can't be found instead of stopping at the first. A table with
+/// only a compiled `build/<name>.table.bin` counts as present.
+pub fn missing_tables(name: &str) -> Vec<MissingTable> {
+    let mut missing = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(name.to_string(), None)];
+    while let Some((table, imported_by)) = pending.pop() {
+        if !visited.insert(table.clone()) {
+            continue;
+        }
+        let found = dict_filename(&table).ok().and_then(|f| resolve_config_path(&f));
+        match found {
+            Some((path, _)) => {
+                let imports = read_header(&path).map(|h| string_list(h.get("import_tables"))).unwrap_or_default();
+                pending.extend(imports.into_iter().rev().map(|i| (i, Some(table.clone()))));
+            }
+            None if resolve_config_path(&format!("build/{}.table.bin", table)).is_some() => {}
+            None => missing.push(MissingTable { name: table, imported_by }),
+        }
+    }
+    missing
+}
+
+/// Search a dictionary (and with `include_imports`, the tables it imports)
+/// through its index. Results come table by table, in file order.
+pub fn search(name: &str, query: &DictQuery, include_imports: bool, offset: usize, limit: usize) -> Result<DictPage, RimeError> {
+    let names: Vec<String> = if include_imports {
+        resolve_tables(name)?.into_iter().map(|t| t.id).collect()
+    } else {
+        vec![name.to_string()]
+    };
+
+    let mut total = 0;
+    let mut entries = Vec::new();
+    for table in names {
+        let index = dict_index::load(&table)?;
+        let matching = index.matching(query);
+        let skip = offset.saturating_sub(total);
+        let room = limit - entries.len();
+        entries.extend(matching.iter().skip(skip).take(room).map(|p| {
+            let (line, entry) = index.entry(*p);
+            DictHit {
+                table: table.clone(),
+                line,
+                entry,
+            }
+        }));
+        total += matching.len();
+    }
+    Ok(DictPage { total, entries })
+}
+
+/// Append an entry to the end of a dictionary.
+pub fn add_entry(name: &str, entry: &DictEntry) -> Result<usize, RimeError> {
+    let mut file = DictFile::load(name)?;
+    validate_entry(&file.path, entry, &file.columns)?;
+    let ending = file.line_ending();
+    if let Some(last) = file.lines.last_mut() {
+        if !last.ends_with('\n') {
+            last.push_str(ending);
+        }
+    }
+    file.lines.push(format!("{}{}", format_entry(entry, &file.columns), ending));
+    let line = file.lines.len();
+    file.save(&format!("dict {}: add {}", name, entry.text))?;
+    Ok(line)
+}
+
+/// Replace the entry at `line`, which must still hold `original`.
+pub fn update_entry(name: &str, line: usize, original: &DictEntry, entry: &DictEntry) -> Result<(), RimeError> {
+    let mut file = DictFile::load(name)?;
+    let index = file.locate(line, original)?;
+    validate_entry(&file.path, entry, &file.columns)?;
+    let ending = if file.lines[index].ends_with("\r\n") {
+        "\r\n"
+    } else if file.lines[index].ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    };
+    file.lines[index] = format!("{}{}", format_entry(entry, &file.columns), ending);
+    file.save(&format!("dict {}: edit {}", name, entry.text))
+}
+
+/// Remove the entry at `line`, which must still hold `original`.
+pub fn delete_entry(name: &str, line: usize, original: &DictEntry) -> Result<(), RimeError> {
+    let mut file = DictFile::load(name)?;
+    let index = file.locate(line, original)?;
+    file.lines.remove(index);
+    file.save(&format!("dict {}: delete {}", name, original.text))
+}
@@ -0,0 +1,303 @@
+//! Search index over a single `*.dict.yaml` table.
+//!
+//! Built on first use by streaming the file once, then kept in memory until
+//! the file's modification time or size changes. Field text lives in one
+//! string arena; entries are sorted by code for prefix lookup by binary
+//! search, and every character maps to the entries whose text contains it,
+//! so a substring query only checks entries sharing its rarest character.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use super::dict::{dict_filename, parse_header, DictEntry, DEFAULT_COLUMNS, HEADER_END};
+use super::error::RimeError;
+use super::paths::resolve_config_path;
+
+/// Conditions an entry must all meet. Empty fields don't filter.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DictQuery {
+    /// Matches a code prefix or a text substring.
+    pub query: Option<String>,
+    /// Code prefix.
+    pub code: Option<String>,
+    /// Text substring.
+    pub text: Option<String>,
+    pub min_weight: Option<f64>,
+    pub max_weight: Option<f64>,
+}
+
+impl DictQuery {
+    fn normalized(&self) -> DictQuery {
+        let clean = |s: &Option<String>| s.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(|s| s.to_string());
+        DictQuery {
+            query: clean(&self.query),
+            code: clean(&self.code),
+            text: clean(&self.text),
+            ..*self
+        }
+    }
+}
+
+/// A field as a range of the arena; empty means absent.
+#[derive(Debug, Clone, Copy, Default)]
+struct Span {
+    start: u32,
+    len: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IndexedEntry {
+    line: u32,
+    text: Span,
+    code: Span,
+    weight: Span,
+    stem: Span,
+    /// `weight` as a number, for range filters; percentages count as their number.
+    weight_value: Option<f64>,
+}
+
+pub struct TableIndex {
+    modified: Option<SystemTime>,
+    size: u64,
+    arena: String,
+    entries: Vec<IndexedEntry>,
+    /// Entry positions ordered by code.
+    by_code: Vec<u32>,
+    /// Entry positions, ascending, whose text contains the character.
+    postings: HashMap<char, Vec<u32>>,
+}
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, Arc<TableIndex>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<TableIndex>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The index of dictionary `name`, rebuilt if the file changed since last use.
+pub fn load(name: &str) -> Result<Arc<TableIndex>, RimeError> {
+    let filename = dict_filename(name)?;
+    let (path, _) = resolve_config_path(&filename)
+        .ok_or_else(|| RimeError::invalid(Path::new(&filename), "未找到词典"))?;
+    let meta = std::fs::metadata(&path).map_err(|e| RimeError::io(&path, e))?;
+    let modified = meta.modified().ok();
+
+    if let Some(index) = cache().lock().ok().and_then(|c| c.get(&path).cloned()) {
+        if index.modified == modified && index.size == meta.len() {
+            return Ok(index);
+        }
+    }
+
+    let index = Arc::new(TableIndex::build(&path, modified, meta.len())?);
+    if let Ok(mut cache) = cache().lock() {
+        cache.insert(path, index.clone());
+    }
+    Ok(index)
+}
+
+/// Drop the cached index of a file that was just written.
+pub fn invalidate(path: &Path) {
+    if let Ok(mut cache) = cache().lock() {
+        cache.remove(path);
+    }
+}
+
+impl TableIndex {
+    fn build(path: &Path, modified: Option<SystemTime>, size: u64) -> Result<Self, RimeError> {
+        let file = std::fs::File::open(path).map_err(|e| RimeError::io(path, e))?;
+        let mut reader = BufReader::new(file);
+
+        let mut line = String::new();
+        let mut line_no: u32 = 0;
+        let mut header = String::new();
+        let mut header_done = false;
+        while !header_done {
+            line.clear();
+            if reader.read_line(&mut line).map_err(|e| RimeError::io(path, e))? == 0 {
+                return Err(RimeError::invalid(path, "缺少头部结束标记 `...`"));
+            }
+            line_no += 1;
+            if line.trim_end() == HEADER_END {
+                header_done = true;
+            } else {
+                header.push_str(&line);
+            }
+        }
+        let header = parse_header(path, &header)?;
+        let columns: Vec<String> = header
+            .get("columns")
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+            .filter(|c: &Vec<String>| !c.is_empty())
+            .unwrap_or_else(|| DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect());
+
+        let mut index = TableIndex {
+            modified,
+            size,
+            arena: String::new(),
+            entries: Vec::new(),
+            by_code: Vec::new(),
+            postings: HashMap::new(),
+        };
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).map_err(|e| RimeError::io(path, e))? == 0 {
+                break;
+            }
+            line_no += 1;
+            let content = line.trim_end_matches(['\r', '\n']);
+            if content.trim().is_empty() || content.starts_with('#') {
+                continue;
+            }
+            index.push(line_no, content, &columns);
+        }
+
+        let mut by_code: Vec<u32> = (0..index.entries.len() as u32).collect();
+        by_code.sort_by(|a, b| index.code_of(*a).cmp(index.code_of(*b)));
+        index.by_code = by_code;
+        Ok(index)
+    }
+
+    fn store(&mut self, field: &str) -> Span {
+        let span = Span {
+            start: self.arena.len() as u32,
+            len: field.len() as u32,
+        };
+        self.arena.push_str(field);
+        span
+    }
+
+    fn push(&mut self, line: u32, content: &str, columns: &[String]) {
+        let mut entry = IndexedEntry {
+            line,
+            text: Span::default(),
+            code: Span::default(),
+            weight: Span::default(),
+            stem: Span::default(),
+            weight_value: None,
+        };
+        for (column, field) in columns.iter().zip(content.split('\t')) {
+            match column.as_str() {
+                "text" => entry.text = self.store(field),
+                "code" => entry.code = self.store(field),
+                "weight" => {
+                    entry.weight = self.store(field);
+                    entry.weight_value = field.trim_end_matches('%').parse().ok();
+                }
+                "stem" => entry.stem = self.store(field),
+                _ => {}
+            }
+        }
+        if entry.text.len == 0 {
+            return;
+        }
+
+        let position = self.entries.len() as u32;
+        let mut chars: Vec<char> = self.field(entry.text).chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        for c in chars {
+            self.postings.entry(c).or_default().push(position);
+        }
+        self.entries.push(entry);
+    }
+
+    fn field(&self, span: Span) -> &str {
+        &self.arena[span.start as usize..(span.start + span.len) as usize]
+    }
+
+    fn optional(&self, span: Span) -> Option<String> {
+        Some(self.field(span).to_string()).filter(|s| !s.is_empty())
+    }
+
+    fn code_of(&self, position: u32) -> &str {
+        self.field(self.entries[position as usize].code)
+    }
+
+    /// The non-empty codes of all entries, in file order.
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| self.field(e.code)).filter(|c| !c.is_empty())
+    }
+
+    /// Positions of entries whose code starts with `prefix`.
+    fn code_prefix(&self, prefix: &str) -> Vec<u32> {
+        let start = self.by_code.partition_point(|p| self.code_of(*p) < prefix);
+        self.by_code[start..]
+            .iter()
+            .copied()
+            .take_while(|p| self.code_of(*p).starts_with(prefix))
+            .collect()
+    }
+
+    /// Positions of entries whose text contains `needle`.
+    fn text_contains(&self, needle: &str) -> Vec<u32> {
+        let rarest = needle
+            .chars()
+            .map(|c| self.postings.get(&c).map(Vec::as_slice).unwrap_or(&[]))
+            .min_by_key(|p| p.len())
+            .unwrap_or(&[]);
+        rarest
+            .iter()
+            .copied()
+            .filter(|p| self.field(self.entries[*p as usize].text).contains(needle))
+            .collect()
+    }
+
+    /// Positions of all entries matching `query`, in file order.
+    pub fn matching(&self, query: &DictQuery) -> Vec<u32> {
+        let query = query.normalized();
+
+        let mut candidates = if let Some(code) = &query.code {
+            self.code_prefix(code)
+        } else if let Some(text) = &query.text {
+            self.text_contains(text)
+        } else if let Some(q) = &query.query {
+            let mut both = self.code_prefix(q);
+            both.extend(self.text_contains(q));
+            both
+        } else {
+            (0..self.entries.len() as u32).collect()
+        };
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        candidates.retain(|p| {
+            let entry = &self.entries[*p as usize];
+            let code = self.field(entry.code);
+            let text = self.field(entry.text);
+            query.code.as_deref().map(|c| code.starts_with(c)).unwrap_or(true)
+                && query.text.as_deref().map(|t| text.contains(t)).unwrap_or(true)
+                && query
+                    .query
+                    .as_deref()
+                    .map(|q| code.starts_with(q) || text.contains(q))
+                    .unwrap_or(true)
+                && query
+                    .min_weight
+                    .map(|min| entry.weight_value.map(|w| w >= min).unwrap_or(false))
+                    .unwrap_or(true)
+                && query
+                    .max_weight
+                    .map(|max| entry.weight_value.map(|w| w <= max).unwrap_or(false))
+                    .unwrap_or(true)
+        });
+        candidates
+    }
+
+    /// The entry at `position` and its 1-based line number.
+    pub fn entry(&self, position: u32) -> (usize, DictEntry) {
+        let e = &self.entries[position as usize];
+        (
+            e.line as usize,
+            DictEntry {
+                text: self.field(e.text).to_string(),
+                code: self.optional(e.code),
+                weight: self.optional(e.weight),
+                stem: self.optional(e.stem),
+            },
+        )
+    }
+}
@@ -0,0 +1,102 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use super::paths::Layer;
+
+/// Errors surfaced to the UI when reading or writing Rime files.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RimeError {
+    /// The file exists but could not be read or written.
+    Io { path: String, message: String },
+    /// The file is not valid YAML (or JSON, for the app's own files).
+    Parse {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        column: Option<usize>,
+        message: String,
+    },
+    /// The file parses but its content cannot be used (bad reference, wrong type, ...).
+    Invalid {
+        path: String,
+        /// The data directory `path` is in, when the file was looked up in both.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        layer: Option<Layer>,
+        message: String,
+    },
+}
+
+impl RimeError {
+    pub fn io(path: &Path, err: std::io::Error) -> Self {
+        RimeError::Io {
+            path: path.to_string_lossy().to_string(),
+            message: err.to_string(),
+        }
+    }
+
+    pub fn parse(path: &Path, err: serde_yaml::Error) -> Self {
+        let location = err.location();
+        RimeError::Parse {
+            path: path.to_string_lossy().to_string(),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            message: err.to_string(),
+        }
+    }
+
+    /// A parse error in one of Rime's line-based text formats.
+    pub fn parse_line(path: &Path, line: usize, message: impl Into<String>) -> Self {
+        RimeError::Parse {
+            path: path.to_string_lossy().to_string(),
+            line: Some(line),
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn json(path: &Path, err: serde_json::Error) -> Self {
+        RimeError::Parse {
+            path: path.to_string_lossy().to_string(),
+            line: Some(err.line()),
+            column: Some(err.column()),
+            message: err.to_string(),
+        }
+    }
+
+    pub fn invalid(path: &Path, message: impl Into<String>) -> Self {
+        RimeError::Invalid {
+            path: path.to_string_lossy().to_string(),
+            layer: None,
+            message: message.into(),
+        }
+    }
+
+    /// Like `invalid`, for a file resolved from the user or shared directory.
+    pub fn invalid_in(path: &Path, layer: Layer, message: impl Into<String>) -> Self {
+        RimeError::Invalid {
+            path: path.to_string_lossy().to_string(),
+            layer: Some(layer),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RimeError::Io { path, message } => write!(f, "{}: {}", path, message),
+            RimeError::Parse { path, line: Some(line), column: Some(column), message } => {
+                write!(f, "{}:{}:{}: {}", path, line, column, message)
+            }
+            RimeError::Parse { path, line: Some(line), message, .. } => {
+                write!(f, "{}:{}: {}", path, line, message)
+            }
+            RimeError::Parse { path, message, .. } => write!(f, "{}: {}", path, message),
+            RimeError::Invalid { path, message, .. } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for RimeError {}
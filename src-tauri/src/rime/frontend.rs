@@ -0,0 +1,355 @@
+//! Rime frontends (the input method hosting librime) and how to drive each one.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use super::offline::{self, BuildDirs};
+
+pub trait Frontend: Sync {
+    /// Stable identifier, matching `distribution_code_name` in installation.yaml.
+    fn id(&self) -> &'static str;
+
+    /// Display name for the UI.
+    fn name(&self) -> &'static str;
+
+    /// Whether this frontend is installed on the current machine.
+    fn detect(&self) -> bool;
+
+    /// The Rime user directory this frontend reads, if it has a fixed one.
+    fn config_dir(&self) -> Option<PathBuf>;
+
+    /// Ask the frontend to redeploy `config_dir`. Returns a message for the UI.
+    fn deploy(&self, config_dir: &Path) -> Result<String, String>;
+
+    /// Ask the frontend to sync user data with the other installations under
+    /// `sync_dir`. Returns a message for the UI.
+    fn sync(&self, _config_dir: &Path) -> Result<String, String> {
+        Err(format!("{} 不支持从外部触发同步", self.name()))
+    }
+
+    /// The shared data directory holding this frontend's stock `default.yaml`
+    /// and schemas, consulted after the user directory.
+    fn shared_dir(&self) -> Option<PathBuf> {
+        offline::default_shared_dir()
+    }
+
+    /// Directories where librime writes its glog files (`rime.*.INFO` etc.)
+    /// for this frontend. Used to verify a deploy, see `deploy::trigger_deploy`.
+    fn log_dirs(&self) -> Vec<PathBuf> {
+        vec![std::env::temp_dir()]
+    }
+}
+
+pub struct Squirrel;
+pub struct Weasel;
+pub struct Fcitx5Rime;
+pub struct FcitxRime;
+pub struct IbusRime;
+pub struct StandaloneDeployer;
+
+/// All known frontends, in detection priority order.
+pub fn all_frontends() -> &'static [&'static dyn Frontend] {
+    &[
+        &Squirrel,
+        &Weasel,
+        &Fcitx5Rime,
+        &FcitxRime,
+        &IbusRime,
+        &StandaloneDeployer,
+    ]
+}
+
+/// The frontend in use on this machine, detected once and cached.
+///
+/// Among installed frontends, one whose user directory already exists wins;
+/// otherwise the first installed one in priority order.
+pub fn detect_frontend() -> Option<&'static dyn Frontend> {
+    static DETECTED: OnceLock<Option<&'static dyn Frontend>> = OnceLock::new();
+    *DETECTED.get_or_init(|| {
+        let installed: Vec<&'static dyn Frontend> = all_frontends()
+            .iter()
+            .copied()
+            .filter(|f| f.detect())
+            .collect();
+        installed
+            .iter()
+            .copied()
+            .find(|f| f.config_dir().map(|d| d.is_dir()).unwrap_or(false))
+            .or_else(|| installed.first().copied())
+    })
+}
+
+impl Frontend for Squirrel {
+    fn id(&self) -> &'static str {
+        "squirrel"
+    }
+
+    fn name(&self) -> &'static str {
+        "鼠须管"
+    }
+
+    fn detect(&self) -> bool {
+        cfg!(target_os = "macos") && squirrel_app().is_some()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join("Library").join("Rime"))
+    }
+
+    fn deploy(&self, _config_dir: &Path) -> Result<String, String> {
+        let app = squirrel_app().ok_or("未找到 Squirrel.app")?;
+        run(Command::new(app.join("Contents").join("MacOS").join("Squirrel")).arg("--reload"))?;
+        Ok("已通知鼠须管重新部署".into())
+    }
+
+    fn sync(&self, _config_dir: &Path) -> Result<String, String> {
+        let app = squirrel_app().ok_or("未找到 Squirrel.app")?;
+        run(Command::new(app.join("Contents").join("MacOS").join("Squirrel")).arg("--sync"))?;
+        Ok("已通知鼠须管同步用户数据".into())
+    }
+
+    fn shared_dir(&self) -> Option<PathBuf> {
+        squirrel_app().map(|app| app.join("Contents").join("SharedSupport"))
+    }
+
+    fn log_dirs(&self) -> Vec<PathBuf> {
+        let temp = std::env::temp_dir();
+        vec![temp.join("rime.squirrel"), temp]
+    }
+}
+
+fn squirrel_app() -> Option<PathBuf> {
+    let system = PathBuf::from("/Library/Input Methods/Squirrel.app");
+    let user = dirs::home_dir().map(|h| h.join("Library/Input Methods/Squirrel.app"));
+    std::iter::once(system).chain(user).find(|p| p.is_dir())
+}
+
+impl Frontend for Weasel {
+    fn id(&self) -> &'static str {
+        "weasel"
+    }
+
+    fn name(&self) -> &'static str {
+        "小狼毫"
+    }
+
+    fn detect(&self) -> bool {
+        cfg!(target_os = "windows") && weasel_root().is_some()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        std::env::var_os("APPDATA")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join("AppData").join("Roaming")))
+            .map(|appdata| appdata.join("Rime"))
+    }
+
+    fn deploy(&self, _config_dir: &Path) -> Result<String, String> {
+        let root = weasel_root().ok_or("未找到小狼毫安装目录")?;
+        run(Command::new(root.join("WeaselDeployer.exe")).arg("/deploy"))?;
+        Ok("小狼毫已重新部署".into())
+    }
+
+    fn sync(&self, _config_dir: &Path) -> Result<String, String> {
+        let root = weasel_root().ok_or("未找到小狼毫安装目录")?;
+        run(Command::new(root.join("WeaselDeployer.exe")).arg("/sync"))?;
+        Ok("小狼毫已同步用户数据".into())
+    }
+
+    fn shared_dir(&self) -> Option<PathBuf> {
+        weasel_root().map(|root| root.join("data"))
+    }
+
+    fn log_dirs(&self) -> Vec<PathBuf> {
+        let temp = std::env::temp_dir();
+        vec![temp.join("rime.weasel"), temp]
+    }
+}
+
+/// Weasel's install dir: the `WeaselRoot` registry value, else the newest
+/// `weasel-*` folder under Program Files.
+fn weasel_root() -> Option<PathBuf> {
+    for key in [
+        "HKLM\\SOFTWARE\\WOW6432Node\\Rime\\Weasel",
+        "HKLM\\SOFTWARE\\Rime\\Weasel",
+    ] {
+        let output = Command::new("reg")
+            .args(["query", key, "/v", "WeaselRoot"])
+            .output();
+        if let Ok(output) = output {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let root = stdout
+                .lines()
+                .find(|l| l.trim_start().starts_with("WeaselRoot"))
+                .and_then(|l| l.split("REG_SZ").nth(1))
+                .map(|v| PathBuf::from(v.trim()));
+            if let Some(root) = root.filter(|r| r.join("WeaselDeployer.exe").is_file()) {
+                return Some(root);
+            }
+        }
+    }
+
+    let mut candidates: Vec<PathBuf> = ["ProgramFiles(x86)", "ProgramFiles"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(|pf| PathBuf::from(pf).join("Rime"))
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .map(|n| n.to_string_lossy().starts_with("weasel-"))
+                .unwrap_or(false)
+                && p.join("WeaselDeployer.exe").is_file()
+        })
+        .collect();
+    candidates.sort_by_key(|p| version_key(&p.to_string_lossy()));
+    candidates.pop()
+}
+
+/// Numeric components of a version-bearing name, for sorting `weasel-0.9.30` < `weasel-0.15.0`.
+fn version_key(name: &str) -> Vec<u64> {
+    name.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
+
+impl Frontend for Fcitx5Rime {
+    fn id(&self) -> &'static str {
+        "fcitx5-rime"
+    }
+
+    fn name(&self) -> &'static str {
+        "Fcitx5 Rime"
+    }
+
+    fn detect(&self) -> bool {
+        cfg!(target_os = "linux")
+            && which("fcitx5").is_some()
+            && (Path::new("/usr/share/fcitx5/addon/rime.conf").is_file()
+                || self.config_dir().map(|d| d.is_dir()).unwrap_or(false))
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join("fcitx5").join("rime"))
+    }
+
+    fn deploy(&self, _config_dir: &Path) -> Result<String, String> {
+        // Reloading the rime addon runs a maintenance pass that rebuilds changed files.
+        let reloaded = run(Command::new("dbus-send").args([
+            "--session",
+            "--print-reply",
+            "--dest=org.fcitx.Fcitx5",
+            "/controller",
+            "org.fcitx.Fcitx.Controller1.ReloadAddonConfig",
+            "string:rime",
+        ]));
+        if reloaded.is_err() {
+            run(Command::new("fcitx5-remote").arg("-r"))?;
+        }
+        Ok("Fcitx5 Rime 已重新部署".into())
+    }
+}
+
+impl Frontend for FcitxRime {
+    fn id(&self) -> &'static str {
+        "fcitx-rime"
+    }
+
+    fn name(&self) -> &'static str {
+        "Fcitx Rime"
+    }
+
+    fn detect(&self) -> bool {
+        cfg!(target_os = "linux") && which("fcitx").is_some() && which("fcitx-remote").is_some()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("fcitx").join("rime"))
+    }
+
+    fn deploy(&self, _config_dir: &Path) -> Result<String, String> {
+        run(Command::new("fcitx-remote").arg("-r"))?;
+        Ok("Fcitx Rime 已重新部署".into())
+    }
+}
+
+impl Frontend for IbusRime {
+    fn id(&self) -> &'static str {
+        "ibus-rime"
+    }
+
+    fn name(&self) -> &'static str {
+        "IBus Rime"
+    }
+
+    fn detect(&self) -> bool {
+        cfg!(target_os = "linux")
+            && which("ibus").is_some()
+            && Path::new("/usr/share/ibus/component/rime.xml").is_file()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("ibus").join("rime"))
+    }
+
+    fn deploy(&self, _config_dir: &Path) -> Result<String, String> {
+        // ibus-rime has no deploy command; it runs maintenance when the engine starts.
+        run(Command::new("ibus").arg("restart"))?;
+        Ok("IBus 已重启, Rime 将重新部署".into())
+    }
+}
+
+impl Frontend for StandaloneDeployer {
+    fn id(&self) -> &'static str {
+        "rime_deployer"
+    }
+
+    fn name(&self) -> &'static str {
+        "rime_deployer"
+    }
+
+    fn detect(&self) -> bool {
+        offline::is_available()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn deploy(&self, config_dir: &Path) -> Result<String, String> {
+        offline::build(&BuildDirs::resolve(config_dir.to_path_buf(), None, None))
+    }
+
+    fn sync(&self, config_dir: &Path) -> Result<String, String> {
+        offline::sync(&BuildDirs::resolve(config_dir.to_path_buf(), None, None))
+    }
+}
+
+/// Run a command to completion, turning a non-zero exit into its stderr.
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        })
+    }
+}
+
+/// Locate an executable on PATH.
+pub fn which(program: &str) -> Option<PathBuf> {
+    let exe = if cfg!(target_os = "windows") {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(&exe))
+            .find(|p| p.is_file())
+    })
+}
@@ -0,0 +1,27 @@
+use std::path::Path;
+use super::error::RimeError;
+
+/// Atomic write: write to a temp file next to `path`, then rename over it.
+pub fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> Result<(), RimeError> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(
+        "{}.tmp.{}",
+        file_name,
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    ));
+
+    std::fs::write(&temp_path, content).map_err(|e| RimeError::io(&temp_path, e))?;
+
+    if let Err(e) = std::fs::rename(&temp_path, path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(RimeError::io(path, e));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,433 @@
+//! Optional git history for the config dir, behind the `git` cargo feature.
+//!
+//! Once the config dir is a repository (see `init_repo`), every save made
+//! through the app is committed with a message describing the change, and the
+//! log doubles as an audit trail that can be browsed and reverted.
+
+use serde::Serialize;
+use serde_json::Value;
+use super::history::{diff_lines, DiffKind, DiffLine};
+
+pub use imp::{diff_commit, init_repo, log, record, revert_commit, status};
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GitStatus {
+    /// Whether the app was built with the `git` feature.
+    pub available: bool,
+    /// Whether the config dir is a git repository.
+    pub initialized: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitInfo {
+    pub id: String,
+    pub summary: String,
+    pub message: String,
+    pub author: String,
+    /// Seconds since the Unix epoch.
+    pub time: i64,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FileDiff {
+    pub file: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Commit message for a custom.yaml save, e.g. `patch default: menu/page_size 5→9`.
+pub fn describe_patch(basename: &str, old: &Value, new: &Value) -> String {
+    const MAX_KEYS: usize = 3;
+
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let mut changes: Vec<String> = Vec::new();
+    for (key, value) in new {
+        match old.get(key) {
+            Some(previous) if previous == value => {}
+            Some(previous) => changes.push(format!("{} {}→{}", key, brief(previous), brief(value))),
+            None => changes.push(format!("{} →{}", key, brief(value))),
+        }
+    }
+    for (key, previous) in old {
+        if !new.contains_key(key) {
+            changes.push(format!("{} {}→∅", key, brief(previous)));
+        }
+    }
+
+    if changes.is_empty() {
+        return format!("patch {}: no changes", basename);
+    }
+    let more = changes.len().saturating_sub(MAX_KEYS);
+    changes.truncate(MAX_KEYS);
+    let mut message = format!("patch {}: {}", basename, changes.join(", "));
+    if more > 0 {
+        message.push_str(&format!(" (+{} more)", more));
+    }
+    message
+}
+
+/// Short form of a value for a commit summary.
+fn brief(value: &Value) -> String {
+    const MAX_CHARS: usize = 24;
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    if text.chars().count() > MAX_CHARS {
+        let cut: String = text.chars().take(MAX_CHARS).collect();
+        format!("{}…", cut)
+    } else {
+        text
+    }
+}
+
+/// Commit message for a custom_phrase.txt save, e.g. `phrases: +2 -1`.
+pub fn describe_lines(label: &str, old: &str, new: &str) -> String {
+    let mut added = 0;
+    let mut removed = 0;
+    for line in diff_lines(old, new) {
+        match line.kind {
+            DiffKind::Insert => added += 1,
+            DiffKind::Delete => removed += 1,
+            DiffKind::Equal => {}
+        }
+    }
+    format!("{}: +{} -{}", label, added, removed)
+}
+
+#[cfg(feature = "git")]
+mod imp {
+    use git2::{Commit, DiffOptions, Index, IndexAddOption, IndexEntry, IndexTime, Oid, Repository, Signature, Sort, Tree};
+    use std::path::{Path, PathBuf};
+    use super::{CommitInfo, FileDiff, GitStatus};
+    use crate::rime::error::RimeError;
+    use crate::rime::fs_utils::write_atomic;
+    use crate::rime::history::{diff_lines, snapshot};
+    use crate::rime::paths::get_rime_config_dir;
+
+    /// Build products, user dictionaries and the app's own data stay out of git.
+    const GITIGNORE: &str = "build/\n*.userdb/\n*.userdb.txt\nsync/\n.rime-app/\n";
+
+    fn relative_to<'a>(path: &'a Path, dir: &Path) -> &'a Path {
+        path.strip_prefix(dir).unwrap_or(path)
+    }
+
+    fn git_error(dir: &Path) -> impl Fn(git2::Error) -> RimeError + '_ {
+        move |e| RimeError::invalid(dir, e.message().to_string())
+    }
+
+    /// The config dir's repository, if it is one. Parent repositories don't count.
+    fn open(dir: &Path) -> Result<Option<Repository>, RimeError> {
+        if !dir.join(".git").exists() {
+            return Ok(None);
+        }
+        Repository::open(dir).map(Some).map_err(git_error(dir))
+    }
+
+    fn open_required(dir: &Path) -> Result<Repository, RimeError> {
+        open(dir)?.ok_or_else(|| RimeError::invalid(dir, "配置目录还不是 git 仓库"))
+    }
+
+    fn signature(repo: &Repository) -> Result<Signature<'static>, git2::Error> {
+        repo.signature()
+            .or_else(|_| Signature::now("RIME Config", "rime-app@localhost"))
+    }
+
+    fn head_commit(repo: &Repository) -> Option<Commit<'_>> {
+        repo.head().ok().and_then(|h| h.peel_to_commit().ok())
+    }
+
+    /// Save `index` and commit it, unless it matches HEAD. Returns the new commit id.
+    fn commit_index(repo: &Repository, index: &mut Index, message: &str) -> Result<Option<Oid>, git2::Error> {
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        commit_tree(repo, &tree, message)
+    }
+
+    /// Commit `tree` on top of HEAD, unless it matches HEAD's.
+    fn commit_tree(repo: &Repository, tree: &Tree, message: &str) -> Result<Option<Oid>, git2::Error> {
+        let parent = head_commit(repo);
+        if parent.as_ref().map(|p| p.tree_id() == tree.id()).unwrap_or(false) {
+            return Ok(None);
+        }
+        let sig = signature(repo)?;
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, tree, &parents)
+            .map(Some)
+    }
+
+    fn blob_entry(path: &Path, mode: u32, id: Oid, size: usize) -> IndexEntry {
+        IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            file_size: size as u32,
+            id,
+            flags: 0,
+            flags_extended: 0,
+            path: path.to_string_lossy().replace('\\', "/").into_bytes(),
+        }
+    }
+
+    /// Commit HEAD's tree with only `files` (relative to `dir`) updated from
+    /// disk, or removed where the file is gone.
+    ///
+    /// The tree is built in an in-memory index, so whatever else the user has
+    /// staged stays staged and out of the commit. In the repository's index
+    /// only `files` are updated, to match the commit.
+    fn commit_files(repo: &Repository, dir: &Path, files: &[PathBuf], message: &str) -> Result<Option<Oid>, git2::Error> {
+        let mut tree_index = Index::new()?;
+        if let Some(head) = head_commit(repo) {
+            tree_index.read_tree(&head.tree()?)?;
+        }
+        let mut index = repo.index()?;
+        for file in files {
+            let path = dir.join(file);
+            if path.is_file() {
+                let content = std::fs::read(&path).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+                let mode = tree_index.get_path(file, 0).map(|e| e.mode).unwrap_or(0o100644);
+                let id = repo.blob(&content)?;
+                tree_index.add(&blob_entry(file, mode, id, content.len()))?;
+                index.add_path(file)?;
+            } else {
+                tree_index.remove_path(file)?;
+                index.remove_path(file)?;
+            }
+        }
+        let tree = repo.find_tree(tree_index.write_tree_to(repo)?)?;
+        index.write()?;
+        commit_tree(repo, &tree, message)
+    }
+
+    pub fn status() -> GitStatus {
+        let dir = get_rime_config_dir();
+        GitStatus {
+            available: true,
+            initialized: open(&dir).ok().flatten().is_some(),
+        }
+    }
+
+    /// Turn the config dir into a repository and commit its current content.
+    pub fn init_repo() -> Result<(), RimeError> {
+        let dir = get_rime_config_dir();
+        if open(&dir)?.is_some() {
+            return Ok(());
+        }
+        let repo = Repository::init(&dir).map_err(git_error(&dir))?;
+
+        let ignore = dir.join(".gitignore");
+        if !ignore.exists() {
+            write_atomic(&ignore, GITIGNORE)?;
+        }
+
+        let mut index = repo.index().map_err(git_error(&dir))?;
+        index
+            .add_all(["*"], IndexAddOption::DEFAULT, None)
+            .map_err(git_error(&dir))?;
+        commit_index(&repo, &mut index, "Initial config").map_err(git_error(&dir))?;
+        Ok(())
+    }
+
+    /// Commit `paths`, and nothing else the user may have staged. Does nothing
+    /// when the config dir is not a repository. Failures are logged, never
+    /// returned: the save itself already succeeded.
+    pub fn record(paths: &[&Path], message: &str) {
+        let dir = get_rime_config_dir();
+        let result = (|| -> Result<(), RimeError> {
+            let Some(repo) = open(&dir)? else {
+                return Ok(());
+            };
+            let files: Vec<PathBuf> = paths.iter().map(|p| relative_to(p, &dir).to_path_buf()).collect();
+            commit_files(&repo, &dir, &files, message).map_err(git_error(&dir))?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            log::warn!("git commit failed: {}", e);
+        }
+    }
+
+    fn changed_files(repo: &Repository, old: Option<&Tree>, new: &Tree) -> Result<Vec<PathBuf>, git2::Error> {
+        let diff = repo.diff_tree_to_tree(old, Some(new), Some(&mut DiffOptions::new()))?;
+        Ok(diff
+            .deltas()
+            .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()).map(Path::to_path_buf))
+            .collect())
+    }
+
+    /// Most recent commits first.
+    pub fn log(limit: usize) -> Result<Vec<CommitInfo>, RimeError> {
+        let dir = get_rime_config_dir();
+        let Some(repo) = open(&dir)? else {
+            return Ok(vec![]);
+        };
+        if head_commit(&repo).is_none() {
+            return Ok(vec![]);
+        }
+        let err = git_error(&dir);
+
+        let mut walk = repo.revwalk().map_err(&err)?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).map_err(&err)?;
+        walk.push_head().map_err(&err)?;
+
+        let mut commits = Vec::new();
+        for oid in walk.take(limit) {
+            let commit = repo.find_commit(oid.map_err(&err)?).map_err(&err)?;
+            let tree = commit.tree().map_err(&err)?;
+            let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+            let files = changed_files(&repo, parent_tree.as_ref(), &tree).map_err(&err)?;
+            commits.push(CommitInfo {
+                id: commit.id().to_string(),
+                summary: commit.summary().unwrap_or("").to_string(),
+                message: commit.message().unwrap_or("").to_string(),
+                author: commit.author().name().unwrap_or("").to_string(),
+                time: commit.time().seconds(),
+                files: files.iter().map(|f| f.to_string_lossy().to_string()).collect(),
+            });
+        }
+        Ok(commits)
+    }
+
+    fn find_commit<'r>(repo: &'r Repository, dir: &Path, id: &str) -> Result<Commit<'r>, RimeError> {
+        Oid::from_str(id)
+            .and_then(|oid| repo.find_commit(oid))
+            .map_err(git_error(dir))
+    }
+
+    fn blob_text(repo: &Repository, tree: Option<&Tree>, path: &Path) -> String {
+        tree.and_then(|t| t.get_path(path).ok())
+            .and_then(|entry| repo.find_blob(entry.id()).ok())
+            .map(|blob| String::from_utf8_lossy(blob.content()).to_string())
+            .unwrap_or_default()
+    }
+
+    /// Per-file line diff of a commit against its parent.
+    pub fn diff_commit(id: &str) -> Result<Vec<FileDiff>, RimeError> {
+        let dir = get_rime_config_dir();
+        let repo = open_required(&dir)?;
+        let err = git_error(&dir);
+
+        let commit = find_commit(&repo, &dir, id)?;
+        let tree = commit.tree().map_err(&err)?;
+        let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+
+        Ok(changed_files(&repo, parent_tree.as_ref(), &tree)
+            .map_err(&err)?
+            .into_iter()
+            .map(|file| {
+                let old = blob_text(&repo, parent_tree.as_ref(), &file);
+                let new = blob_text(&repo, Some(&tree), &file);
+                FileDiff {
+                    file: file.to_string_lossy().to_string(),
+                    lines: diff_lines(&old, &new),
+                }
+            })
+            .collect())
+    }
+
+    /// Undo a commit with a new commit on top of HEAD.
+    ///
+    /// The revert is computed in memory first, so a conflict leaves the config
+    /// dir untouched. Files with uncommitted edits are refused rather than
+    /// overwritten; the files that do change are snapshotted before writing.
+    pub fn revert_commit(id: &str) -> Result<(), RimeError> {
+        let dir = get_rime_config_dir();
+        let repo = open_required(&dir)?;
+        let err = git_error(&dir);
+
+        let commit = find_commit(&repo, &dir, id)?;
+        if commit.parent_count() > 1 {
+            return Err(RimeError::invalid(&dir, "不支持还原合并提交"));
+        }
+        let head = head_commit(&repo).ok_or_else(|| RimeError::invalid(&dir, "仓库没有提交"))?;
+
+        let mut reverted = repo.revert_commit(&commit, &head, 0, None).map_err(&err)?;
+        if reverted.has_conflicts() {
+            return Err(RimeError::invalid(&dir, "还原与之后的修改冲突"));
+        }
+        let new_tree = repo
+            .find_tree(reverted.write_tree_to(&repo).map_err(&err)?)
+            .map_err(&err)?;
+        let head_tree = head.tree().map_err(&err)?;
+        let files = changed_files(&repo, Some(&head_tree), &new_tree).map_err(&err)?;
+
+        for file in &files {
+            let status = repo.status_file(file).map_err(&err)?;
+            if !status.is_empty() {
+                return Err(RimeError::invalid(&dir.join(file), "文件有未提交的修改"));
+            }
+        }
+
+        for file in &files {
+            let path = dir.join(file);
+            snapshot(&path)?;
+            match new_tree.get_path(file) {
+                Ok(entry) => {
+                    let blob = repo.find_blob(entry.id()).map_err(&err)?;
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent).map_err(|e| RimeError::io(parent, e))?;
+                    }
+                    write_atomic(&path, blob.content())?;
+                }
+                Err(_) => {
+                    if path.exists() {
+                        std::fs::remove_file(&path).map_err(|e| RimeError::io(&path, e))?;
+                    }
+                }
+            }
+        }
+
+        let message = format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.",
+            commit.summary().unwrap_or(""),
+            commit.id()
+        );
+        commit_files(&repo, &dir, &files, &message).map_err(&err)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "git"))]
+mod imp {
+    use std::path::Path;
+    use super::{CommitInfo, FileDiff, GitStatus};
+    use crate::rime::error::RimeError;
+    use crate::rime::paths::get_rime_config_dir;
+
+    fn unavailable() -> RimeError {
+        RimeError::invalid(&get_rime_config_dir(), "此版本未启用 git 功能")
+    }
+
+    pub fn status() -> GitStatus {
+        GitStatus {
+            available: false,
+            initialized: false,
+        }
+    }
+
+    pub fn init_repo() -> Result<(), RimeError> {
+        Err(unavailable())
+    }
+
+    pub fn record(_paths: &[&Path], _message: &str) {}
+
+    pub fn log(_limit: usize) -> Result<Vec<CommitInfo>, RimeError> {
+        Ok(vec![])
+    }
+
+    pub fn diff_commit(_id: &str) -> Result<Vec<FileDiff>, RimeError> {
+        Err(unavailable())
+    }
+
+    pub fn revert_commit(_id: &str) -> Result<(), RimeError> {
+        Err(unavailable())
+    }
+}
@@ -0,0 +1,211 @@
+//! Snapshot history for files the app writes.
+//!
+//! Before a file in the config dir is overwritten, its current content is
+//! copied to `<config_dir>/.rime-app/history/<relative path>/<millis>`. Snapshots
+//! can be listed, diffed against the current file and restored.
+
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::git;
+use super::paths::{get_config_path, get_rime_config_dir};
+
+const APP_DIR: &str = ".rime-app";
+
+/// Snapshots kept per file, newest first.
+const MAX_SNAPSHOTS: usize = 50;
+/// Snapshots older than this are dropped, except the newest one.
+const MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub id: String,
+    pub file: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a snapshot diff; line numbers are 1-based.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// History folder of a file given relative to the config dir.
+fn history_dir(filename: &str) -> Result<PathBuf, RimeError> {
+    let relative = Path::new(filename);
+    let inside = relative.components().count() > 0
+        && relative.components().all(|c| matches!(c, Component::Normal(_)));
+    if !inside {
+        return Err(RimeError::invalid(relative, "只能是配置目录中的文件"));
+    }
+    Ok(get_rime_config_dir().join(APP_DIR).join("history").join(relative))
+}
+
+fn snapshot_path(filename: &str, id: &str) -> Result<PathBuf, RimeError> {
+    let dir = history_dir(filename)?;
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RimeError::invalid(&dir, format!("无效的快照: {}", id)));
+    }
+    let path = dir.join(id);
+    if !path.is_file() {
+        return Err(RimeError::invalid(&path, "快照不存在"));
+    }
+    Ok(path)
+}
+
+/// Copy the current content of `path` into its history before it gets
+/// overwritten. Does nothing if the file doesn't exist yet, is unchanged
+/// since the last snapshot, or lives outside the config dir.
+pub fn snapshot(path: &Path) -> Result<Option<Snapshot>, RimeError> {
+    let config_dir = get_rime_config_dir();
+    let Ok(relative) = path.strip_prefix(&config_dir) else {
+        return Ok(None);
+    };
+    let filename = relative.to_string_lossy().replace('\\', "/");
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(RimeError::io(path, e)),
+    };
+
+    let existing = list_snapshots(&filename)?;
+    if let Some(latest) = existing.first() {
+        let latest_path = history_dir(&filename)?.join(&latest.id);
+        if std::fs::read(&latest_path).ok().as_deref() == Some(content.as_slice()) {
+            return Ok(None);
+        }
+    }
+
+    let dir = history_dir(&filename)?;
+    std::fs::create_dir_all(&dir).map_err(|e| RimeError::io(&dir, e))?;
+
+    // Two saves within the same millisecond must not share an id.
+    let mut created_at = now_millis();
+    if let Some(latest) = existing.first() {
+        created_at = created_at.max(latest.created_at + 1);
+    }
+    let id = created_at.to_string();
+    write_atomic(&dir.join(&id), &content)?;
+
+    prune(&filename)?;
+
+    Ok(Some(Snapshot {
+        id,
+        file: filename,
+        created_at,
+        size: content.len() as u64,
+    }))
+}
+
+/// Snapshots of a file in the config dir (e.g. `default.custom.yaml`), newest first.
+pub fn list_snapshots(filename: &str) -> Result<Vec<Snapshot>, RimeError> {
+    let dir = history_dir(filename)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(RimeError::io(&dir, e)),
+    };
+
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_string_lossy().to_string();
+            let created_at = id.parse::<u64>().ok()?;
+            let size = entry.metadata().ok()?.len();
+            Some(Snapshot {
+                id,
+                file: filename.to_string(),
+                created_at,
+                size,
+            })
+        })
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    Ok(snapshots)
+}
+
+/// Line diff from a snapshot (old) to the current file (new).
+pub fn diff_snapshot(filename: &str, id: &str) -> Result<Vec<DiffLine>, RimeError> {
+    let old_path = snapshot_path(filename, id)?;
+    let old = std::fs::read_to_string(&old_path).map_err(|e| RimeError::io(&old_path, e))?;
+
+    let current_path = get_config_path(filename);
+    let new = match std::fs::read_to_string(&current_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(RimeError::io(&current_path, e)),
+    };
+
+    Ok(diff_lines(&old, &new))
+}
+
+/// Line diff between two texts.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Equal => DiffKind::Equal,
+                ChangeTag::Insert => DiffKind::Insert,
+                ChangeTag::Delete => DiffKind::Delete,
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect()
+}
+
+/// Put a snapshot back in place. The current content is snapshotted first,
+/// so a restore can itself be undone.
+pub fn restore_snapshot(filename: &str, id: &str) -> Result<(), RimeError> {
+    let source = snapshot_path(filename, id)?;
+    let content = std::fs::read(&source).map_err(|e| RimeError::io(&source, e))?;
+
+    let target = get_config_path(filename);
+    snapshot(&target)?;
+    write_atomic(&target, content)?;
+
+    git::record(&[&target], &format!("restore {} from snapshot {}", filename, id));
+    Ok(())
+}
+
+/// Apply the retention policy to a file's history.
+fn prune(filename: &str) -> Result<(), RimeError> {
+    let dir = history_dir(filename)?;
+    let cutoff = now_millis().saturating_sub(MAX_AGE.as_millis() as u64);
+    for (index, snapshot) in list_snapshots(filename)?.iter().enumerate() {
+        if index >= MAX_SNAPSHOTS || (index > 0 && snapshot.created_at < cutoff) {
+            let path = dir.join(&snapshot.id);
+            std::fs::remove_file(&path).map_err(|e| RimeError::io(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
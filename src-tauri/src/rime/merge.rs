@@ -0,0 +1,497 @@
+//! Rime config compiler.
+//!
+//! Resolves a config the way librime's `ConfigCompiler` does at deploy time:
+//! `__include` pulls in a referenced node and overlays the sibling keys on it,
+//! `__patch` applies path-keyed edits, `__append`/`key/+` extend lists,
+//! `__merge`/`key/+` merge maps, and `<id>.custom.yaml` is applied to every
+//! non-custom resource as an implicit `__patch: <id>.custom:/patch?`.
+//!
+//! References take the form `[resource_id:]path[?]`, e.g. `default:/menu`,
+//! `rime_ice.schema:/switches` or `/key_binder/bindings`. A trailing `?`
+//! marks the reference optional. List elements are addressed with `@N`,
+//! `@last`, `@next`, `@before N` and `@after N`.
+//!
+//! Resources resolve from the user directory first, then the shared data
+//! directory, like librime's resource resolver.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use super::error::RimeError;
+use super::paths::{get_base_filename, get_config_path, resolve_config_path, Layer};
+use super::yaml_utils::{convert_bgr_colors, read_yaml_file};
+
+const INCLUDE_DIRECTIVE: &str = "__include";
+const PATCH_DIRECTIVE: &str = "__patch";
+const APPEND_DIRECTIVE: &str = "__append";
+const MERGE_DIRECTIVE: &str = "__merge";
+const ADD_SUFFIX_OPERATOR: &str = "/+";
+const EQU_SUFFIX_OPERATOR: &str = "/=";
+
+/// The effective config of a resource and the files that contributed to it.
+#[derive(Debug, Clone)]
+pub struct MergedConfig {
+    pub config: Value,
+    pub sources: Vec<ConfigSource>,
+}
+
+/// A file that contributed to a compiled config, and the layer it came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSource {
+    pub file: String,
+    pub layer: Layer,
+}
+
+/// Compile the effective config for a basename (e.g. `default`, `squirrel`, `rime_ice`).
+pub fn compile_config(basename: &str) -> Result<MergedConfig, RimeError> {
+    let filename = get_base_filename(basename);
+    let resource_id = filename.trim_end_matches(".yaml");
+
+    let mut compiler = ConfigCompiler::default();
+    let config = compiler.compile_resource(resource_id)?;
+    let config = if basename == "squirrel" {
+        convert_bgr_colors(config)
+    } else {
+        config
+    };
+
+    Ok(MergedConfig {
+        config,
+        sources: compiler.sources,
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Reference {
+    resource_id: String,
+    path: String,
+    optional: bool,
+}
+
+impl Reference {
+    fn parse(qualified: &str, current_resource: &str) -> Reference {
+        let (qualified, optional) = match qualified.strip_suffix('?') {
+            Some(rest) => (rest, true),
+            None => (qualified, false),
+        };
+        let (resource_id, path) = match qualified.split_once(':') {
+            Some((res, path)) if !res.is_empty() => (res.to_string(), path),
+            Some((_, path)) => (current_resource.to_string(), path),
+            None => (current_resource.to_string(), qualified),
+        };
+        Reference {
+            resource_id,
+            path: path.trim_start_matches('/').to_string(),
+            optional,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ConfigCompiler {
+    /// Raw documents as parsed from disk, `None` when the file is missing.
+    raw: HashMap<String, Option<Value>>,
+    /// Fully compiled resources, including their auto patch.
+    compiled: HashMap<String, Value>,
+    /// Resources currently being compiled, for cycle detection.
+    stack: Vec<String>,
+    sources: Vec<ConfigSource>,
+    /// Where each resource that exists was read from, for errors.
+    files: HashMap<String, (PathBuf, Layer)>,
+}
+
+impl ConfigCompiler {
+    fn load_raw(&mut self, resource_id: &str) -> Result<Option<Value>, RimeError> {
+        if let Some(doc) = self.raw.get(resource_id) {
+            return Ok(doc.clone());
+        }
+        let filename = format!("{}.yaml", resource_id);
+        let doc = match resolve_config_path(&filename) {
+            Some((path, layer)) => {
+                let doc = read_yaml_file(&path, false)?;
+                if doc.is_some() {
+                    self.sources.push(ConfigSource { file: filename, layer });
+                }
+                self.files.insert(resource_id.to_string(), (path, layer));
+                doc
+            }
+            None => None,
+        };
+        self.raw.insert(resource_id.to_string(), doc.clone());
+        Ok(doc)
+    }
+
+    /// An error in `resource_id`, at the file it was read from; a resource
+    /// that doesn't exist is reported where the user would create it.
+    fn invalid(&self, resource_id: &str, message: impl Into<String>) -> RimeError {
+        match self.files.get(resource_id) {
+            Some((path, layer)) => RimeError::invalid_in(path, *layer, message),
+            None => RimeError::invalid(&get_config_path(&format!("{}.yaml", resource_id)), message),
+        }
+    }
+
+    /// Compile a whole resource and apply its `.custom` patch.
+    fn compile_resource(&mut self, resource_id: &str) -> Result<Value, RimeError> {
+        if let Some(done) = self.compiled.get(resource_id) {
+            return Ok(done.clone());
+        }
+        if self.stack.iter().any(|r| r == resource_id) {
+            return Err(self.invalid(
+                resource_id,
+                format!("循环引用: {} -> {}", self.stack.join(" -> "), resource_id),
+            ));
+        }
+
+        let raw = self
+            .load_raw(resource_id)?
+            .ok_or_else(|| self.invalid(resource_id, "找不到配置文件"))?;
+
+        self.stack.push(resource_id.to_string());
+        let result = self.compile_root(resource_id, raw);
+        self.stack.pop();
+
+        let compiled = result?;
+        self.compiled.insert(resource_id.to_string(), compiled.clone());
+        Ok(compiled)
+    }
+
+    fn compile_root(&mut self, resource_id: &str, raw: Value) -> Result<Value, RimeError> {
+        let mut root = self.compile_node(resource_id, raw)?;
+
+        if !resource_id.ends_with(".custom") {
+            let patch_id = format!("{}.custom", resource_id.trim_end_matches(".schema"));
+            let reference = Reference {
+                resource_id: patch_id,
+                path: "patch".into(),
+                optional: true,
+            };
+            if let Some(patch) = self.resolve_reference(&reference)? {
+                apply_patch(&mut root, patch).map_err(|e| self.invalid(&reference.resource_id, e))?;
+            }
+        }
+
+        Ok(strip_directives(root))
+    }
+
+    fn compile_node(&mut self, resource_id: &str, node: Value) -> Result<Value, RimeError> {
+        match node {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| self.compile_node(resource_id, item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            Value::Object(map) => self.compile_map(resource_id, map),
+            other => Ok(other),
+        }
+    }
+
+    fn compile_map(&mut self, resource_id: &str, mut map: Map<String, Value>) -> Result<Value, RimeError> {
+        let include = map.shift_remove(INCLUDE_DIRECTIVE);
+        let patch = map.shift_remove(PATCH_DIRECTIVE);
+
+        let mut compiled = Map::new();
+        for (key, value) in map {
+            compiled.insert(key, self.compile_node(resource_id, value)?);
+        }
+
+        let mut node = match include {
+            Some(target) => {
+                let mut node = Value::Null;
+                let references =
+                    reference_list(&target, resource_id).map_err(|e| self.invalid(resource_id, e))?;
+                for reference in references {
+                    if let Some(included) = self.resolve_reference(&reference)? {
+                        node = included;
+                    }
+                }
+                // Sibling keys of `__include` are overlaid in merge-tree mode.
+                for (key, value) in compiled {
+                    edit_node(&mut node, &key, value, true).map_err(|e| self.invalid(resource_id, e))?;
+                }
+                if node.is_null() {
+                    Value::Object(Map::new())
+                } else {
+                    node
+                }
+            }
+            None => Value::Object(compiled),
+        };
+
+        if let Some(patch) = patch {
+            let patches = match patch {
+                Value::Array(items) => items,
+                other => vec![other],
+            };
+            for item in patches {
+                match item {
+                    Value::String(target) => {
+                        let reference = Reference::parse(&target, resource_id);
+                        if let Some(patch) = self.resolve_reference(&reference)? {
+                            apply_patch(&mut node, patch).map_err(|e| self.invalid(resource_id, e))?;
+                        }
+                    }
+                    Value::Object(_) => {
+                        let patch = self.compile_node(resource_id, item)?;
+                        apply_patch(&mut node, patch).map_err(|e| self.invalid(resource_id, e))?;
+                    }
+                    Value::Null => {}
+                    other => return Err(self.invalid(resource_id, format!("无效的 __patch: {}", other))),
+                }
+            }
+        }
+
+        Ok(node)
+    }
+
+    /// Resolve a reference to a compiled node. Returns `None` for a missing optional target.
+    fn resolve_reference(&mut self, reference: &Reference) -> Result<Option<Value>, RimeError> {
+        let in_progress = self.stack.iter().any(|r| r == &reference.resource_id);
+
+        let root = if in_progress {
+            // Local reference into a resource being compiled: compile the raw subtree.
+            let raw = self.load_raw(&reference.resource_id)?.unwrap_or(Value::Null);
+            match find_node(&raw, &reference.path) {
+                Some(node) => {
+                    let resource_id = reference.resource_id.clone();
+                    return self.compile_node(&resource_id, node.clone()).map(Some);
+                }
+                None => None,
+            }
+        } else if self.load_raw(&reference.resource_id)?.is_some() {
+            let compiled = self.compile_resource(&reference.resource_id)?;
+            find_node(&compiled, &reference.path).cloned()
+        } else {
+            None
+        };
+
+        match root {
+            Some(node) => Ok(Some(node)),
+            None if reference.optional => Ok(None),
+            // Reported in the file holding the reference, the one being compiled.
+            None => Err(self.invalid(
+                self.stack.last().unwrap_or(&reference.resource_id),
+                format!("无法解析引用: {}:/{}", reference.resource_id, reference.path),
+            )),
+        }
+    }
+}
+
+fn reference_list(target: &Value, resource_id: &str) -> Result<Vec<Reference>, String> {
+    match target {
+        Value::String(s) => Ok(vec![Reference::parse(s, resource_id)]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(|s| Reference::parse(s, resource_id))
+                    .ok_or_else(|| format!("无效的 __include: {}", item))
+            })
+            .collect(),
+        other => Err(format!("无效的 __include: {}", other)),
+    }
+}
+
+/// Apply a patch map: every key is a path, map values replace unless the key ends with `/+`.
+/// Keys apply in document order, so `@next` and `@N` edits see the ones before them.
+fn apply_patch(target: &mut Value, patch: Value) -> Result<(), String> {
+    match patch {
+        Value::Object(map) => {
+            for (key, value) in map {
+                edit_node(target, &key, value, false)?;
+            }
+            Ok(())
+        }
+        Value::Null => Ok(()),
+        other => Err(format!("patch 必须是映射: {}", other)),
+    }
+}
+
+fn is_appending(key: &str) -> bool {
+    key == APPEND_DIRECTIVE || key.ends_with(ADD_SUFFIX_OPERATOR)
+}
+
+fn is_merging(key: &str, value: &Value, merge_tree: bool) -> bool {
+    key == MERGE_DIRECTIVE
+        || key.ends_with(ADD_SUFFIX_OPERATOR)
+        || (merge_tree && value.is_object() && !key.ends_with(EQU_SUFFIX_OPERATOR))
+}
+
+/// librime's `EditNode`: locate (creating as needed) the node at `key` and
+/// assign, append or merge `value` into it.
+fn edit_node(head: &mut Value, key: &str, value: Value, merge_tree: bool) -> Result<(), String> {
+    let appending = is_appending(key);
+    let merging = is_merging(key, &value, merge_tree);
+
+    let path = if key == APPEND_DIRECTIVE || key == MERGE_DIRECTIVE {
+        ""
+    } else {
+        key.strip_suffix(ADD_SUFFIX_OPERATOR)
+            .or_else(|| key.strip_suffix(EQU_SUFFIX_OPERATOR))
+            .unwrap_or(key)
+    };
+
+    let target = locate_mut(head, path)?;
+
+    // `key: { __append: [...] }` / `key: { __merge: {...} }` edit the existing node.
+    if let Value::Object(map) = &value {
+        if map.contains_key(APPEND_DIRECTIVE) || map.contains_key(MERGE_DIRECTIVE) {
+            if let Value::Object(map) = value {
+                for (k, v) in map {
+                    edit_node(target, &k, v, true)?;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    if appending && (value.is_array() || target.is_array()) {
+        append_to_list(target, value, key)
+    } else if merging && value.is_object() {
+        merge_tree_into(target, value, key)
+    } else {
+        *target = value;
+        Ok(())
+    }
+}
+
+fn append_to_list(target: &mut Value, value: Value, key: &str) -> Result<(), String> {
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Null => return Ok(()),
+        other => return Err(format!("{}: 追加的值必须是列表, 实际为 {}", key, other)),
+    };
+    match target {
+        Value::Array(list) => {
+            list.extend(items);
+            Ok(())
+        }
+        Value::Null => {
+            *target = Value::Array(items);
+            Ok(())
+        }
+        _ => Err(format!("{}: 目标节点不是列表", key)),
+    }
+}
+
+fn merge_tree_into(target: &mut Value, value: Value, key: &str) -> Result<(), String> {
+    if target.is_null() {
+        *target = Value::Object(Map::new());
+    }
+    if !target.is_object() {
+        return Err(format!("{}: 目标节点不是映射", key));
+    }
+    if let Value::Object(map) = value {
+        for (k, v) in map {
+            edit_node(target, &k, v, true)?;
+        }
+    }
+    Ok(())
+}
+
+enum ListIndex {
+    At(usize),
+    Next,
+    Before(usize),
+}
+
+fn parse_list_index(segment: &str, len: usize) -> Option<ListIndex> {
+    let spec = segment.strip_prefix('@')?;
+    let parse_pos = |s: &str| -> Option<usize> {
+        match s.trim() {
+            "last" => len.checked_sub(1),
+            n => n.parse().ok(),
+        }
+    };
+    if spec == "next" {
+        Some(ListIndex::Next)
+    } else if let Some(rest) = spec.strip_prefix("before ") {
+        parse_pos(rest).map(ListIndex::Before)
+    } else if let Some(rest) = spec.strip_prefix("after ") {
+        parse_pos(rest).map(|i| ListIndex::Before(i + 1))
+    } else {
+        parse_pos(spec).map(ListIndex::At)
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// Walk `path` from `root` for mutation, creating maps and list slots along the way.
+fn locate_mut<'a>(root: &'a mut Value, path: &str) -> Result<&'a mut Value, String> {
+    let mut node = root;
+    for segment in split_path(path) {
+        if segment.starts_with('@') {
+            if node.is_null() {
+                *node = Value::Array(vec![]);
+            }
+            let list = node
+                .as_array_mut()
+                .ok_or_else(|| format!("{}: 目标节点不是列表", path))?;
+            let index = match parse_list_index(segment, list.len()) {
+                Some(ListIndex::At(i)) if i < list.len() => i,
+                Some(ListIndex::At(i)) if i == list.len() => {
+                    list.push(Value::Null);
+                    i
+                }
+                Some(ListIndex::Next) => {
+                    list.push(Value::Null);
+                    list.len() - 1
+                }
+                Some(ListIndex::Before(i)) if i <= list.len() => {
+                    list.insert(i, Value::Null);
+                    i
+                }
+                _ => return Err(format!("{}: 无效的列表下标 {}", path, segment)),
+            };
+            node = &mut list[index];
+        } else {
+            if node.is_null() {
+                *node = Value::Object(Map::new());
+            }
+            let map = node
+                .as_object_mut()
+                .ok_or_else(|| format!("{}: 目标节点不是映射", path))?;
+            node = map.entry(segment.to_string()).or_insert(Value::Null);
+        }
+    }
+    Ok(node)
+}
+
+/// Look up the node at `path` without modifying anything.
+fn find_node<'a>(root: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut node = root;
+    for segment in split_path(path) {
+        node = match node {
+            Value::Array(list) => match parse_list_index(segment, list.len())? {
+                ListIndex::At(i) => list.get(i)?,
+                _ => return None,
+            },
+            Value::Object(map) => map.get(segment)?,
+            _ => return None,
+        };
+    }
+    Some(node)
+}
+
+/// Resolve any `__append`/`__merge` left without a target into plain values.
+fn strip_directives(node: Value) -> Value {
+    match node {
+        Value::Array(items) => Value::Array(items.into_iter().map(strip_directives).collect()),
+        Value::Object(mut map) => {
+            if let Some(list) = map.remove(APPEND_DIRECTIVE) {
+                return strip_directives(list);
+            }
+            if let Some(merged) = map.remove(MERGE_DIRECTIVE) {
+                return strip_directives(merged);
+            }
+            Value::Object(
+                map.into_iter()
+                    .map(|(k, v)| (k, strip_directives(v)))
+                    .collect(),
+            )
+        }
+        other => other,
+    }
+}
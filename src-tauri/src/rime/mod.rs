@@ -1,7 +1,22 @@
+pub mod error;
 pub mod paths;
+pub mod profiles;
 pub mod yaml_utils;
+pub mod fs_utils;
+pub mod yaml_edit;
 pub mod reader;
+pub mod history;
+pub mod git;
 pub mod writer;
+pub mod merge;
+pub mod algebra;
 pub mod schemas;
+pub mod schema_list;
+pub mod frontend;
+pub mod offline;
 pub mod deploy;
+pub mod sync;
+pub mod userdb;
+pub mod dict;
+pub mod dict_index;
 pub mod phrases;
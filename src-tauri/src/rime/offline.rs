@@ -0,0 +1,213 @@
+//! Offline deployment: compile a Rime user directory without a running input
+//! method, through the `rime_deployer` tool shipped with librime or, with the
+//! `librime` cargo feature, by calling librime directly.
+
+use std::path::PathBuf;
+#[cfg(not(feature = "librime"))]
+use std::process::Command;
+use super::frontend::which;
+use super::paths::get_shared_data_dir;
+
+/// Directories handed to librime's deployer.
+#[derive(Debug, Clone)]
+pub struct BuildDirs {
+    pub user_dir: PathBuf,
+    pub shared_dir: PathBuf,
+    pub staging_dir: PathBuf,
+}
+
+impl BuildDirs {
+    /// Fill in defaults: the frontend's shared data dir (or the user dir itself)
+    /// as shared dir, and `<user_dir>/build` as staging dir.
+    pub fn resolve(user_dir: PathBuf, shared_dir: Option<PathBuf>, staging_dir: Option<PathBuf>) -> Self {
+        let shared_dir = shared_dir
+            .or_else(get_shared_data_dir)
+            .unwrap_or_else(|| user_dir.clone());
+        let staging_dir = staging_dir.unwrap_or_else(|| user_dir.join("build"));
+        BuildDirs {
+            user_dir,
+            shared_dir,
+            staging_dir,
+        }
+    }
+}
+
+/// The shared data dir of a system-wide librime install, if any.
+pub fn default_shared_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RIME_SHARED_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    [
+        "/usr/share/rime-data",
+        "/usr/local/share/rime-data",
+        "/opt/homebrew/share/rime-data",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .find(|p| p.is_dir())
+}
+
+/// Whether offline deployment is possible on this machine.
+pub fn is_available() -> bool {
+    cfg!(feature = "librime") || which("rime_deployer").is_some()
+}
+
+/// Compile `dirs.user_dir` into `dirs.staging_dir`. Blocks until done.
+#[cfg(not(feature = "librime"))]
+pub fn build(dirs: &BuildDirs) -> Result<String, String> {
+    let program = which("rime_deployer").ok_or("未找到 rime_deployer")?;
+    std::fs::create_dir_all(&dirs.staging_dir).map_err(|e| e.to_string())?;
+
+    let output = Command::new(program)
+        .arg("--build")
+        .arg(&dirs.user_dir)
+        .arg(&dirs.shared_dir)
+        .arg(&dirs.staging_dir)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok("rime_deployer 已完成编译".into())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        })
+    }
+}
+
+/// Compile `dirs.user_dir` into `dirs.staging_dir`. Blocks until done.
+#[cfg(feature = "librime")]
+pub fn build(dirs: &BuildDirs) -> Result<String, String> {
+    std::fs::create_dir_all(&dirs.staging_dir).map_err(|e| e.to_string())?;
+    ffi::deploy_workspace(dirs)?;
+    Ok("librime 已完成编译".into())
+}
+
+/// Sync user data through `sync_dir`. `rime_deployer` has no sync mode, so
+/// this needs the `librime` feature.
+#[cfg(not(feature = "librime"))]
+pub fn sync(_dirs: &BuildDirs) -> Result<String, String> {
+    Err("rime_deployer 不支持同步, 需启用 librime 特性".into())
+}
+
+/// Sync user data through `sync_dir`, as librime's `RimeSyncUserData` does.
+#[cfg(feature = "librime")]
+pub fn sync(dirs: &BuildDirs) -> Result<String, String> {
+    ffi::sync_user_data(dirs)?;
+    Ok("librime 已完成同步".into())
+}
+
+#[cfg(feature = "librime")]
+mod ffi {
+    use super::BuildDirs;
+    use std::ffi::{c_char, c_int, CString};
+    use std::path::Path;
+    use std::sync::{Mutex, OnceLock};
+
+    /// `RimeTraits` from rime_api.h.
+    #[repr(C)]
+    struct RimeTraits {
+        data_size: c_int,
+        shared_data_dir: *const c_char,
+        user_data_dir: *const c_char,
+        distribution_name: *const c_char,
+        distribution_code_name: *const c_char,
+        distribution_version: *const c_char,
+        app_name: *const c_char,
+        modules: *const *const c_char,
+        min_log_level: c_int,
+        log_dir: *const c_char,
+        prebuilt_data_dir: *const c_char,
+        staging_dir: *const c_char,
+    }
+
+    #[link(name = "rime")]
+    extern "C" {
+        fn RimeSetup(traits: *mut RimeTraits);
+        fn RimeDeployerInitialize(traits: *mut RimeTraits);
+        fn RimeDeployWorkspace() -> c_int;
+        fn RimeRunTask(task_name: *const c_char) -> c_int;
+    }
+
+    /// librime keeps global state; only one deployment may run at a time.
+    static LOCK: Mutex<()> = Mutex::new(());
+    /// `RimeSetup` initializes glog, which aborts if initialized twice, so it
+    /// runs once per process and librime is never finalized.
+    static SETUP: OnceLock<()> = OnceLock::new();
+
+    fn c_path(path: &Path) -> Result<CString, String> {
+        CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())
+    }
+
+    fn c_str(s: &str) -> Result<CString, String> {
+        CString::new(s).map_err(|e| e.to_string())
+    }
+
+    /// Point librime's deployer at `dirs` and run `work` against it.
+    fn with_deployer(dirs: &BuildDirs, work: impl FnOnce() -> bool) -> Result<bool, String> {
+        let _guard = LOCK.lock().map_err(|e| e.to_string())?;
+
+        let shared = c_path(&dirs.shared_dir)?;
+        let user = c_path(&dirs.user_dir)?;
+        let staging = c_path(&dirs.staging_dir)?;
+        let log_dir = c_path(&std::env::temp_dir())?;
+        let app_name = c_str("rime.rime-app")?;
+        let dist_name = c_str("RIME Config")?;
+        let dist_code = c_str("rime-app")?;
+        let dist_version = c_str(env!("CARGO_PKG_VERSION"))?;
+
+        let mut traits = RimeTraits {
+            data_size: (std::mem::size_of::<RimeTraits>() - std::mem::size_of::<c_int>()) as c_int,
+            shared_data_dir: shared.as_ptr(),
+            user_data_dir: user.as_ptr(),
+            distribution_name: dist_name.as_ptr(),
+            distribution_code_name: dist_code.as_ptr(),
+            distribution_version: dist_version.as_ptr(),
+            app_name: app_name.as_ptr(),
+            modules: std::ptr::null(),
+            min_log_level: 1,
+            log_dir: log_dir.as_ptr(),
+            prebuilt_data_dir: shared.as_ptr(),
+            staging_dir: staging.as_ptr(),
+        };
+
+        // SAFETY: all pointers in `traits` outlive these calls; librime copies
+        // the strings. `LOCK` serializes access to librime's global deployer.
+        let ok = unsafe {
+            SETUP.get_or_init(|| RimeSetup(&mut traits));
+            RimeDeployerInitialize(&mut traits);
+            work()
+        };
+        Ok(ok)
+    }
+
+    pub fn deploy_workspace(dirs: &BuildDirs) -> Result<(), String> {
+        // SAFETY: runs inside `with_deployer`, after the deployer is initialized.
+        let ok = with_deployer(dirs, || unsafe { RimeDeployWorkspace() != 0 })?;
+        if ok {
+            Ok(())
+        } else {
+            Err("librime 部署失败".into())
+        }
+    }
+
+    /// The tasks `RimeSyncUserData` schedules, run synchronously.
+    pub fn sync_user_data(dirs: &BuildDirs) -> Result<(), String> {
+        let tasks = ["installation_update", "backup_config_files", "user_dict_sync"]
+            .map(|task| CString::new(task).map_err(|e| e.to_string()))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        // SAFETY: runs inside `with_deployer`; the task names outlive the calls.
+        let ok = with_deployer(dirs, || {
+            tasks.iter().all(|task| unsafe { RimeRunTask(task.as_ptr()) != 0 })
+        })?;
+        if ok {
+            Ok(())
+        } else {
+            Err("librime 同步失败".into())
+        }
+    }
+}
@@ -1,16 +1,57 @@
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use super::frontend::{all_frontends, detect_frontend};
+use super::offline::default_shared_dir;
+use super::profiles::active_config_dir;
+use super::yaml_utils::read_yaml_file;
 
+/// The directory resolved by `resolve_config_dir`. Resolving reads
+/// profiles.json and may scan Documents, Downloads and Desktop, so it's done
+/// once and redone only after `invalidate_config_dir`.
+static RESOLVED_CONFIG_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The Rime user directory every command operates on: `RIME_CONFIG_DIR`,
+/// else the active profile, else the detected frontend's directory if it
+/// exists, else the best candidate from `discover_config_dirs`.
 pub fn get_rime_config_dir() -> PathBuf {
     if let Ok(dir) = std::env::var("RIME_CONFIG_DIR") {
         return PathBuf::from(dir);
     }
+    let mut resolved = RESOLVED_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    resolved.get_or_insert_with(resolve_config_dir).clone()
+}
+
+/// Forget the resolved directory, e.g. after the active profile changed.
+pub fn invalidate_config_dir() {
+    *RESOLVED_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn resolve_config_dir() -> PathBuf {
+    if let Some(dir) = active_config_dir() {
+        return dir;
+    }
+
+    let detected = detect_frontend().and_then(|f| f.config_dir());
+    if let Some(dir) = detected.as_ref().filter(|d| d.is_dir()) {
+        return dir.clone();
+    }
+
+    if let Some(candidate) = discover_config_dirs().into_iter().next() {
+        return PathBuf::from(candidate.path);
+    }
+
+    if let Some(dir) = detected {
+        return dir;
+    }
 
     let home = dirs::home_dir().unwrap_or_default();
 
     if cfg!(target_os = "macos") {
         home.join("Library").join("Rime")
     } else if cfg!(target_os = "linux") {
-        home.join(".config").join("fcitx").join("rime")
+        home.join(".local").join("share").join("fcitx5").join("rime")
     } else if cfg!(target_os = "windows") {
         if let Ok(appdata) = std::env::var("APPDATA") {
             PathBuf::from(appdata).join("Rime")
@@ -22,10 +63,44 @@ pub fn get_rime_config_dir() -> PathBuf {
     }
 }
 
+/// The shared data directory librime falls back to for files missing from the
+/// user directory: `RIME_SHARED_DATA_DIR`, else the detected frontend's, else
+/// a system-wide `rime-data`.
+pub fn get_shared_data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RIME_SHARED_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    detect_frontend()
+        .and_then(|f| f.shared_dir())
+        .filter(|d| d.is_dir())
+        .or_else(default_shared_dir)
+}
+
 pub fn get_config_path(filename: &str) -> PathBuf {
     get_rime_config_dir().join(filename)
 }
 
+/// Which data directory a file was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Layer {
+    User,
+    Shared,
+}
+
+/// Find a file the way librime does: the user directory first, then the
+/// shared data directory.
+pub fn resolve_config_path(filename: &str) -> Option<(PathBuf, Layer)> {
+    let user = get_config_path(filename);
+    if user.is_file() {
+        return Some((user, Layer::User));
+    }
+    get_shared_data_dir()
+        .map(|dir| dir.join(filename))
+        .filter(|p| p.is_file())
+        .map(|p| (p, Layer::Shared))
+}
+
 pub fn get_custom_filename(basename: &str) -> String {
     format!("{}.custom.yaml", basename)
 }
@@ -36,3 +111,166 @@ pub fn get_base_filename(basename: &str) -> String {
         _ => format!("{}.schema.yaml", basename),
     }
 }
+
+/// A directory that looks like a Rime user directory, see `discover_config_dirs`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateDir {
+    pub path: String,
+    /// Owning frontend: a `Frontend::id`, or `trime` / `hamster` for mobile exports.
+    pub frontend: Option<String>,
+    pub frontend_name: Option<String>,
+    /// `distribution_code_name` from the directory's installation.yaml.
+    pub distribution: Option<String>,
+    /// Whether the directory has been deployed at least once (has installation.yaml).
+    pub installed: bool,
+    /// Number of `*.yaml` files at the top level.
+    pub yaml_files: usize,
+}
+
+/// Mobile frontends whose user directories get copied to desktop machines.
+const MOBILE_FRONTENDS: &[(&str, &str)] = &[("trime", "同文输入法"), ("hamster", "仓输入法")];
+
+/// Probe every known Rime user directory location on this machine and return
+/// the existing ones, best candidate first.
+///
+/// Locations come from each frontend's default dir, Flatpak sandboxes, and
+/// Trime/Hamster exports; folders under Documents, Downloads and Desktop are
+/// also picked up when they carry an installation.yaml. Directories that were
+/// deployed and hold more config files rank higher; ties go to the detected
+/// frontend, then to probe order.
+pub fn discover_config_dirs() -> Vec<CandidateDir> {
+    let mut seen = HashSet::new();
+    let mut candidates: Vec<CandidateDir> = known_locations()
+        .into_iter()
+        .filter(|(path, _)| path.is_dir())
+        .filter(|(path, _)| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .map(|(path, owner)| inspect_dir(&path, owner))
+        .collect();
+
+    let detected = detect_frontend().map(|f| f.id());
+    // Stable sort keeps probe order among equals.
+    candidates.sort_by_key(|c| {
+        (
+            !c.installed,
+            c.yaml_files == 0,
+            detected.is_none() || c.frontend.as_deref() != detected,
+        )
+    });
+    candidates
+}
+
+/// Every place a Rime user directory may live, with the frontend it belongs
+/// to when the location alone tells.
+fn known_locations() -> Vec<(PathBuf, Option<&'static str>)> {
+    let mut locations: Vec<(PathBuf, Option<&'static str>)> = all_frontends()
+        .iter()
+        .filter_map(|f| f.config_dir().map(|dir| (dir, Some(f.id()))))
+        .collect();
+
+    let home = dirs::home_dir().unwrap_or_default();
+
+    if cfg!(target_os = "linux") {
+        // Flatpak redirects XDG_DATA_HOME into the sandbox.
+        for app in ["org.fcitx.Fcitx5", "org.fcitx.Fcitx5.Addon.Rime"] {
+            let sandbox = home.join(".var").join("app").join(app);
+            locations.push((sandbox.join("data").join("fcitx5").join("rime"), Some("fcitx5-rime")));
+        }
+        locations.push((home.join(".config").join("fcitx").join("rime"), Some("fcitx-rime")));
+        locations.push((home.join(".config").join("ibus").join("rime"), Some("ibus-rime")));
+    }
+
+    if cfg!(target_os = "macos") {
+        // Hamster syncs its user directory through iCloud Drive.
+        let icloud = home.join("Library").join("Mobile Documents");
+        locations.push((
+            icloud.join("iCloud~dev~fuxiao~app~hamsterapp").join("Documents").join("RIME").join("Rime"),
+            Some("hamster"),
+        ));
+    }
+
+    let export_roots: Vec<PathBuf> = [dirs::document_dir(), dirs::download_dir(), dirs::desktop_dir()]
+        .into_iter()
+        .flatten()
+        .collect();
+    for root in &export_roots {
+        locations.push((root.join("rime"), Some("trime")));
+        locations.push((root.join("Rime"), None));
+        locations.push((root.join("Hamster").join("Rime"), Some("hamster")));
+        // Any other folder one level down identified by its installation.yaml.
+        if let Ok(entries) = std::fs::read_dir(root) {
+            locations.extend(
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.join("installation.yaml").is_file())
+                    .map(|p| (p, None)),
+            );
+        }
+    }
+
+    locations
+}
+
+fn inspect_dir(path: &Path, owner: Option<&'static str>) -> CandidateDir {
+    let installation = read_yaml_file(&path.join("installation.yaml"), false)
+        .ok()
+        .flatten();
+    let distribution = installation
+        .as_ref()
+        .and_then(|v| v.get("distribution_code_name"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    let frontend = distribution
+        .as_deref()
+        .and_then(frontend_of_distribution)
+        .filter(|signed| owner.map(|o| same_family(o, signed)) != Some(true))
+        .or(owner);
+
+    let yaml_files = std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().map(|x| x == "yaml").unwrap_or(false))
+                .count()
+        })
+        .unwrap_or(0);
+
+    CandidateDir {
+        path: path.to_string_lossy().to_string(),
+        frontend: frontend.map(|f| f.to_string()),
+        frontend_name: frontend.and_then(frontend_name),
+        distribution,
+        installed: installation.is_some(),
+        yaml_files,
+    }
+}
+
+/// Map an installation.yaml `distribution_code_name` to a frontend id.
+fn frontend_of_distribution(code_name: &str) -> Option<&'static str> {
+    match code_name.to_ascii_lowercase().as_str() {
+        "squirrel" => Some("squirrel"),
+        "weasel" => Some("weasel"),
+        "fcitx-rime" | "fcitx5-rime" => Some("fcitx5-rime"),
+        "ibus-rime" => Some("ibus-rime"),
+        "trime" => Some("trime"),
+        "hamster" => Some("hamster"),
+        _ => None,
+    }
+}
+
+/// fcitx and fcitx5 write the same `distribution_code_name`; keep the
+/// location's owner when the signature only names the family.
+fn same_family(owner: &str, signed: &str) -> bool {
+    owner == signed || (owner.starts_with("fcitx") && signed.starts_with("fcitx"))
+}
+
+fn frontend_name(id: &str) -> Option<String> {
+    all_frontends()
+        .iter()
+        .find(|f| f.id() == id)
+        .map(|f| f.name())
+        .or_else(|| MOBILE_FRONTENDS.iter().find(|(m, _)| *m == id).map(|(_, name)| *name))
+        .map(|name| name.to_string())
+}
@@ -0,0 +1,226 @@
+//! Exporting a custom phrase table to other formats.
+//!
+//! CSV, JSON and macOS text-replacement plists are for other programs; the
+//! `*.dict.yaml` export turns the phrases into a Rime dictionary that a
+//! schema can list in `import_tables`. Comments and unparsed lines of the
+//! table are not exported.
+//!
+//! A pinyin (`script_translator`) dictionary spells each code as syllables
+//! separated by spaces, while custom phrase codes are usually run together
+//! (`nihao`). For those schemas a dictionary export splits each code into the
+//! syllables of the schema's dictionary (`ni hao`), and leaves out and reports
+//! codes that can't be split, such as abbreviations like `dz`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use crate::rime::algebra::simulate::dictionary_syllables;
+use crate::rime::dict::DICT_SUFFIX;
+use crate::rime::error::RimeError;
+use crate::rime::fs_utils::write_atomic;
+use crate::rime::git;
+use crate::rime::history::snapshot;
+use crate::rime::merge::compile_config;
+use crate::rime::paths::get_rime_config_dir;
+use super::import::SkippedLine;
+use super::{format_entry, list_tables, read_phrases, PhraseEntry, PhraseItem};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    MacPlist,
+    /// A `<name>.dict.yaml`; the name comes from the target file name.
+    Dict,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub path: String,
+    pub entries: usize,
+    /// Entries left out of a dictionary export, with the reason.
+    pub skipped: Vec<SkippedLine>,
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn to_csv(entries: &[PhraseEntry]) -> String {
+    let mut out = String::from("phrase,code,weight\n");
+    for entry in entries {
+        let weight = entry.weight.map(|w| w.to_string()).unwrap_or_default();
+        out.push_str(&format!("{},{},{}\n", csv_field(&entry.phrase), csv_field(&entry.code), weight));
+    }
+    out
+}
+
+fn to_plist(entries: &[PhraseEntry]) -> Result<Vec<u8>, String> {
+    let items = entries
+        .iter()
+        .map(|entry| {
+            let mut dict = plist::Dictionary::new();
+            dict.insert("phrase".into(), plist::Value::String(entry.phrase.clone()));
+            dict.insert("shortcut".into(), plist::Value::String(entry.code.clone()));
+            plist::Value::Dictionary(dict)
+        })
+        .collect();
+    let mut out = Vec::new();
+    plist::Value::Array(items).to_writer_xml(&mut out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+fn to_dict(path: &Path, name: &str, table: &str, entries: &[PhraseEntry]) -> Result<String, RimeError> {
+    let mut header = serde_yaml::Mapping::new();
+    header.insert("name".into(), name.into());
+    header.insert("version".into(), "1.0".into());
+    header.insert("sort".into(), "by_weight".into());
+    let header = serde_yaml::to_string(&header).map_err(|e| RimeError::parse(path, e))?;
+
+    let mut out = format!(
+        "# Rime dictionary\n# encoding: utf-8\n#\n# Exported from {}.txt\n\n---\n{}...\n\n",
+        table, header
+    );
+    for entry in entries {
+        out.push_str(&entry.phrase);
+        out.push('\t');
+        out.push_str(&entry.code);
+        if let Some(w) = entry.weight {
+            out.push('\t');
+            out.push_str(&w.to_string());
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The syllables codes must be made of for a dictionary imported by
+/// `schema_id`, or by the first schema loading `table`: those of the
+/// schema's own dictionary, if it spells by syllable (`script_translator`).
+fn dict_syllables(table: &str, schema_id: Option<&str>) -> Result<Option<HashSet<String>>, RimeError> {
+    let schema_id = match schema_id {
+        Some(id) => id.to_string(),
+        None => match list_tables().into_iter().find(|t| t.name == table).and_then(|t| t.schemas.into_iter().next()) {
+            Some(used) => used.schema_id,
+            None => return Ok(None),
+        },
+    };
+    let config = compile_config(&schema_id)?.config;
+    let spells_by_syllable = config
+        .pointer("/engine/translators")
+        .and_then(|v| v.as_array())
+        .map(|list| list.iter().any(|t| t.as_str() == Some("script_translator")))
+        .unwrap_or(false);
+    let dictionary = config.pointer("/translator/dictionary").and_then(|v| v.as_str()).filter(|d| !d.is_empty());
+    match dictionary {
+        Some(dictionary) if spells_by_syllable => Ok(Some(dictionary_syllables(dictionary)?.into_iter().collect())),
+        _ => Ok(None),
+    }
+}
+
+/// Split `code` into the fewest `syllables`, keeping the breaks already
+/// written as spaces or `'`. On failure, returns the part from where no
+/// segmentation gets any further.
+fn segment(code: &str, syllables: &HashSet<String>) -> Result<String, String> {
+    let longest = syllables.iter().map(String::len).max().unwrap_or(0);
+    let mut out = Vec::new();
+    for part in code.split([' ', '\'']).filter(|p| !p.is_empty()) {
+        // best[i]: the fewest syllables spelling `part[..i]`, and where the last one starts.
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; part.len() + 1];
+        best[0] = Some((0, 0));
+        for end in (1..=part.len()).filter(|i| part.is_char_boundary(*i)) {
+            best[end] = (end.saturating_sub(longest)..end)
+                .filter(|start| part.is_char_boundary(*start) && syllables.contains(&part[*start..end]))
+                .filter_map(|start| best[start].map(|(count, _)| (count + 1, start)))
+                .min();
+        }
+        if best[part.len()].is_none() {
+            let reached = (0..=part.len()).rev().find(|i| best[*i].is_some()).unwrap_or(0);
+            return Err(part[reached..].to_string());
+        }
+        let mut pieces = Vec::new();
+        let mut end = part.len();
+        while end > 0 {
+            let (_, start) = best[end].unwrap_or_default();
+            pieces.push(&part[start..end]);
+            end = start;
+        }
+        out.extend(pieces.into_iter().rev());
+    }
+    Ok(out.join(" "))
+}
+
+/// Export the entries of table `table` to `path` in `format`.
+///
+/// A dictionary export must be named `<name>.dict.yaml`; its codes are split
+/// into the syllables of `schema_id` (default: the first schema loading the
+/// table). Targets inside the config dir are snapshotted and recorded like
+/// any other edit.
+pub fn export(table: &str, format: ExportFormat, path: &Path, schema_id: Option<&str>) -> Result<ExportSummary, RimeError> {
+    let mut entries: Vec<(usize, PhraseEntry)> = read_phrases(table)?
+        .items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, item)| match item {
+            PhraseItem::Entry(entry) => Some((i + 1, PhraseEntry { raw: None, ending: None, ..entry })),
+            _ => None,
+        })
+        .collect();
+
+    let mut skipped = Vec::new();
+    if format == ExportFormat::Dict {
+        if let Some(syllables) = dict_syllables(table, schema_id)? {
+            entries.retain_mut(|(line, entry)| match segment(&entry.code, &syllables) {
+                Ok(code) => {
+                    entry.code = code;
+                    true
+                }
+                Err(rest) => {
+                    skipped.push(SkippedLine {
+                        line: *line,
+                        text: format_entry(entry),
+                        reason: format!("编码无法切分为音节: {}", rest),
+                    });
+                    false
+                }
+            });
+        }
+    }
+    let entries: Vec<PhraseEntry> = entries.into_iter().map(|(_, entry)| entry).collect();
+
+    let content = match format {
+        ExportFormat::Csv => to_csv(&entries).into_bytes(),
+        ExportFormat::Json => serde_json::to_vec_pretty(&entries).map_err(|e| RimeError::json(path, e))?,
+        ExportFormat::MacPlist => to_plist(&entries).map_err(|msg| RimeError::invalid(path, msg))?,
+        ExportFormat::Dict => {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .and_then(|n| n.strip_suffix(DICT_SUFFIX).map(str::to_string))
+                .filter(|n| !n.is_empty())
+                .ok_or_else(|| RimeError::invalid(path, "词典文件名必须是 <名称>.dict.yaml"))?;
+            to_dict(path, &name, table, &entries)?.into_bytes()
+        }
+    };
+
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| RimeError::io(dir, e))?;
+    }
+    snapshot(path)?;
+    write_atomic(path, &content)?;
+    if path.starts_with(get_rime_config_dir()) {
+        git::record(&[path], &format!("export {} ({} entries)", table, entries.len()));
+    }
+
+    Ok(ExportSummary {
+        path: path.to_string_lossy().to_string(),
+        entries: entries.len(),
+        skipped,
+    })
+}
@@ -0,0 +1,453 @@
+//! Importing custom phrases exported by other input methods.
+//!
+//! Supported sources:
+//! - Sogou and QQ Pinyin: `code,position=phrase` lines, `;` comments.
+//! - Baidu Pinyin: `code=position,phrase` lines.
+//! - Microsoft Pinyin: the binary `.dat` export (`mschxudp`, UTF-16LE strings).
+//! - macOS text replacements: the `.plist` dragged out of System Settings.
+//! - CSV or TSV with `phrase,code[,weight]` columns, optionally with a header row.
+//!
+//! Text sources may be UTF-8, UTF-16 (with or without BOM) or GBK. Positions
+//! are dropped: entries keep the order of the source, which Rime uses for
+//! entries of equal weight.
+
+use encoding_rs::{Encoding, GBK, UTF_16BE, UTF_16LE, UTF_8};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use crate::rime::error::RimeError;
+use super::{read_phrases, write_phrases, PhraseEntry, PhraseItem};
+
+const MS_PINYIN_MAGIC: &[u8] = b"mschxudp";
+/// Size of the fixed part of an `.dat` entry, before its code.
+const MS_PINYIN_ENTRY_HEADER: usize = 16;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    Sogou,
+    Qq,
+    Baidu,
+    MsPinyin,
+    MacPlist,
+    Csv,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStatus {
+    /// Not in the table yet.
+    New,
+    /// Already in the table, or earlier in the import, with the same weight.
+    Duplicate,
+    /// Already in the table with a different weight.
+    Conflict,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedPhrase {
+    #[serde(flatten)]
+    pub entry: PhraseEntry,
+    pub status: ImportStatus,
+    /// Line (or item number) in the source.
+    pub source_line: usize,
+    /// Line of the matching entry in the table, for duplicates and conflicts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub existing_line: Option<usize>,
+}
+
+/// A source line that didn't convert to an entry.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedLine {
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub format: ImportFormat,
+    /// The detected text encoding, `None` for binary formats.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    pub entries: Vec<ImportedPhrase>,
+    pub skipped: Vec<SkippedLine>,
+}
+
+#[derive(Default)]
+struct Parsed {
+    entries: Vec<(usize, PhraseEntry)>,
+    skipped: Vec<SkippedLine>,
+}
+
+impl Parsed {
+    fn push(&mut self, line: usize, phrase: &str, code: &str, weight: Option<i64>) {
+        if phrase.contains(['\t', '\r', '\n']) || code.contains(['\t', '\r', '\n']) {
+            self.skip(line, phrase, "短语含有制表符或换行, 自定义短语不支持多行");
+        } else if phrase.is_empty() || code.is_empty() {
+            self.skip(line, phrase, "缺少短语或编码");
+        } else {
+            let entry = PhraseEntry {
+                phrase: phrase.to_string(),
+                code: code.to_string(),
+                weight,
+                raw: None,
+                ending: None,
+            };
+            self.entries.push((line, entry));
+        }
+    }
+
+    fn skip(&mut self, line: usize, text: &str, reason: &str) {
+        self.skipped.push(SkippedLine {
+            line,
+            text: text.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Decode a text export, trying a BOM, then UTF-16 by its NUL bytes, then
+/// UTF-8, then GBK.
+fn decode(bytes: &[u8]) -> (String, &'static str) {
+    if let Some((encoding, bom)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom..]);
+        return (text.into_owned(), encoding.name());
+    }
+
+    let sample = &bytes[..bytes.len().min(4096)];
+    let nul_at = |parity: usize| sample.iter().skip(parity).step_by(2).filter(|b| **b == 0).count();
+    let half = sample.len() / 2;
+    if half > 0 {
+        for (parity, encoding) in [(1, UTF_16LE), (0, UTF_16BE)] {
+            if nul_at(parity) * 3 > half && nul_at(1 - parity) * 10 < half {
+                let (text, _) = encoding.decode_without_bom_handling(bytes);
+                return (text.into_owned(), encoding.name());
+            }
+        }
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), UTF_8.name()),
+        Err(_) => {
+            let (text, _) = GBK.decode_without_bom_handling(bytes);
+            (text.into_owned(), GBK.name())
+        }
+    }
+}
+
+/// Guess the format from the file extension, then from the content.
+fn detect_format(path: &Path, bytes: &[u8]) -> Option<ImportFormat> {
+    if bytes.starts_with(MS_PINYIN_MAGIC) {
+        return Some(ImportFormat::MsPinyin);
+    }
+    if bytes.starts_with(b"bplist") {
+        return Some(ImportFormat::MacPlist);
+    }
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "plist" => Some(ImportFormat::MacPlist),
+        "csv" | "tsv" => Some(ImportFormat::Csv),
+        "dat" | "lex" => Some(ImportFormat::MsPinyin),
+        "ini" | "txt" => {
+            let (text, _) = decode(bytes);
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with(';') && !l.starts_with('#'))
+                .find_map(|line| {
+                    if parse_position_pair(line, '=', ',').is_some() {
+                        Some(ImportFormat::Sogou)
+                    } else if parse_position_pair(line, ',', '=').is_some() {
+                        Some(ImportFormat::Baidu)
+                    } else {
+                        None
+                    }
+                })
+        }
+        _ => None,
+    }
+}
+
+/// Split `code<inner>position<outer>phrase`, e.g. Sogou's `dz,1=地址`
+/// (`outer` `=`, `inner` `,`) or Baidu's `dz=1,地址`.
+fn parse_position_pair(line: &str, outer: char, inner: char) -> Option<(&str, &str)> {
+    let (left, phrase) = line.split_once(outer)?;
+    let (code, position) = left.split_once(inner)?;
+    let valid = !code.trim().is_empty() && position.trim().parse::<u32>().is_ok();
+    valid.then(|| (code.trim(), phrase))
+}
+
+/// Sogou, QQ and Baidu: one `code,position=phrase` (or Baidu's
+/// `code=position,phrase`) per line.
+fn parse_positional(text: &str, outer: char, inner: char) -> Parsed {
+    let mut parsed = Parsed::default();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') || trimmed.starts_with('[') {
+            continue;
+        }
+        match parse_position_pair(trimmed, outer, inner) {
+            Some((code, phrase)) => parsed.push(line_no, phrase, code, None),
+            None => parsed.skip(line_no, line, "无法识别的行, 多行短语需手动处理"),
+        }
+    }
+    parsed
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<usize> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<usize> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// A NUL-terminated UTF-16LE string.
+fn read_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|u| *u != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Microsoft Pinyin's custom phrase export.
+///
+/// After the `mschxudp` magic and version, the header holds at 0x10 the
+/// offset of the entry offset table, then the offset of the entries, the
+/// file length and the entry count. Each entry starts with 16 bytes whose
+/// u16 at +4 is the offset of the phrase within the entry and whose byte at
+/// +6 is the candidate position; the code follows the header, and both code
+/// and phrase are NUL-terminated UTF-16LE.
+fn parse_ms_pinyin(bytes: &[u8]) -> Result<Parsed, String> {
+    if !bytes.starts_with(MS_PINYIN_MAGIC) {
+        return Err("不是微软拼音自定义短语文件 (.dat); 自学习词库 (.lex) 暂不支持, 请在微软拼音设置中导出自定义短语".into());
+    }
+    let truncated = || "文件已损坏或不完整".to_string();
+    let table_start = read_u32(bytes, 0x10).ok_or_else(truncated)?;
+    let entries_start = read_u32(bytes, 0x14).ok_or_else(truncated)?;
+    let entries_end = read_u32(bytes, 0x18).ok_or_else(truncated)?.min(bytes.len());
+    let count = read_u32(bytes, 0x1c).ok_or_else(truncated)?;
+
+    let offsets = (0..count)
+        .map(|i| read_u32(bytes, table_start + i * 4).map(|o| entries_start + o))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(truncated)?;
+
+    let mut parsed = Parsed::default();
+    for (i, start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(entries_end);
+        let entry = bytes.get(*start..end.max(*start)).ok_or_else(truncated)?;
+        let Some(phrase_at) = read_u16(entry, 4).filter(|p| *p >= MS_PINYIN_ENTRY_HEADER && *p <= entry.len()) else {
+            parsed.skip(i + 1, "", "条目格式无法识别");
+            continue;
+        };
+        let code = read_utf16(&entry[MS_PINYIN_ENTRY_HEADER..phrase_at]);
+        let phrase = read_utf16(&entry[phrase_at..]);
+        parsed.push(i + 1, &phrase, &code, None);
+    }
+    Ok(parsed)
+}
+
+/// macOS text replacements: an array of `{phrase, shortcut}` dictionaries.
+fn parse_mac_plist(bytes: &[u8]) -> Result<Parsed, String> {
+    let value = plist::Value::from_reader(std::io::Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let items = value.as_array().ok_or("文件顶层不是数组, 不是文本替换导出的 plist")?;
+
+    let mut parsed = Parsed::default();
+    for (i, item) in items.iter().enumerate() {
+        let dict = item.as_dictionary();
+        let field = |key: &str| dict.and_then(|d| d.get(key)).and_then(|v| v.as_string()).unwrap_or("");
+        parsed.push(i + 1, field("phrase"), field("shortcut"), None);
+    }
+    Ok(parsed)
+}
+
+/// Split CSV into records of fields; quoted fields may hold delimiters,
+/// doubled quotes and line breaks. Records carry their 1-based start line.
+fn split_csv(text: &str, delimiter: char) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field).trim_end_matches('\r').to_string());
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            c if c == delimiter && !quoted => record.push(std::mem::take(&mut field)),
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    records
+}
+
+/// The column of each field named in a header row, if `row` is one.
+fn csv_header(row: &[String]) -> Option<[Option<usize>; 3]> {
+    let mut columns = [None; 3];
+    for (i, name) in row.iter().enumerate() {
+        let slot = match name.trim().to_lowercase().as_str() {
+            "phrase" | "text" | "短语" | "词条" | "词语" => 0,
+            "code" | "shortcut" | "编码" | "拼音" => 1,
+            "weight" | "权重" | "词频" => 2,
+            _ => continue,
+        };
+        columns[slot].get_or_insert(i);
+    }
+    (columns[0].is_some() && columns[1].is_some()).then_some(columns)
+}
+
+fn parse_csv(text: &str, tab_separated: bool) -> Parsed {
+    let first_line = text.lines().next().unwrap_or("");
+    let delimiter = if tab_separated || (first_line.contains('\t') && !first_line.contains(',')) {
+        '\t'
+    } else {
+        ','
+    };
+    let mut records = split_csv(text, delimiter).into_iter().peekable();
+    let columns = match records.peek().and_then(|(_, row)| csv_header(row)) {
+        Some(columns) => {
+            records.next();
+            columns
+        }
+        None => [Some(0), Some(1), Some(2)],
+    };
+
+    let mut parsed = Parsed::default();
+    for (line, row) in records {
+        if row.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let field = |slot: usize| columns[slot].and_then(|i| row.get(i)).map(|f| f.trim()).unwrap_or("");
+        let weight = match field(2) {
+            "" => None,
+            w => match w.parse::<i64>() {
+                Ok(w) => Some(w),
+                Err(_) => {
+                    parsed.skip(line, &row.join(&delimiter.to_string()), "权重必须是整数");
+                    continue;
+                }
+            },
+        };
+        parsed.push(line, field(0), field(1), weight);
+    }
+    parsed
+}
+
+fn parse(path: &Path, format: Option<ImportFormat>) -> Result<(ImportFormat, Option<String>, Parsed), RimeError> {
+    let bytes = std::fs::read(path).map_err(|e| RimeError::io(path, e))?;
+    let format = format
+        .or_else(|| detect_format(path, &bytes))
+        .ok_or_else(|| RimeError::invalid(path, "无法识别文件格式, 请手动选择"))?;
+
+    let (encoding, parsed) = match format {
+        ImportFormat::MsPinyin => (None, parse_ms_pinyin(&bytes)),
+        ImportFormat::MacPlist => (None, parse_mac_plist(&bytes)),
+        text_format => {
+            let (text, encoding) = decode(&bytes);
+            let parsed = match text_format {
+                ImportFormat::Baidu => parse_positional(&text, ',', '='),
+                ImportFormat::Csv => {
+                    let tsv = path.extension().map(|e| e.eq_ignore_ascii_case("tsv")).unwrap_or(false);
+                    parse_csv(&text, tsv)
+                }
+                _ => parse_positional(&text, '=', ','),
+            };
+            (Some(encoding.to_string()), Ok(parsed))
+        }
+    };
+    let parsed = parsed.map_err(|msg| RimeError::invalid(path, msg))?;
+    Ok((format, encoding, parsed))
+}
+
+/// Convert `path` and compare it with table `table` without writing.
+/// `format` is detected when `None`.
+pub fn preview(table: &str, path: &Path, format: Option<ImportFormat>) -> Result<ImportPreview, RimeError> {
+    let (format, encoding, parsed) = parse(path, format)?;
+    let existing = read_phrases(table)?;
+
+    let mut known: HashMap<(String, String), (Option<i64>, Option<usize>)> = HashMap::new();
+    for (i, item) in existing.items.iter().enumerate() {
+        if let PhraseItem::Entry(e) = item {
+            known.entry((e.phrase.clone(), e.code.clone())).or_insert((e.weight, Some(i + 1)));
+        }
+    }
+
+    let entries = parsed
+        .entries
+        .into_iter()
+        .map(|(source_line, entry)| {
+            let key = (entry.phrase.clone(), entry.code.clone());
+            let (status, existing_line) = match known.get(&key) {
+                Some(&(weight, line)) if weight == entry.weight || line.is_none() => (ImportStatus::Duplicate, line),
+                Some(&(_, line)) => (ImportStatus::Conflict, line),
+                None => {
+                    known.insert(key, (entry.weight, None));
+                    (ImportStatus::New, None)
+                }
+            };
+            ImportedPhrase { entry, status, source_line, existing_line }
+        })
+        .collect();
+
+    Ok(ImportPreview {
+        format,
+        encoding,
+        entries,
+        skipped: parsed.skipped,
+    })
+}
+
+/// Import `path` into table `table`: new entries are appended, conflicts
+/// take the imported weight when `replace_conflicts` is set. Returns the
+/// preview the import was based on.
+pub fn import(
+    table: &str,
+    path: &Path,
+    format: Option<ImportFormat>,
+    replace_conflicts: bool,
+) -> Result<ImportPreview, RimeError> {
+    let preview = preview(table, path, format)?;
+    let mut data = read_phrases(table)?;
+
+    for imported in &preview.entries {
+        match (imported.status, imported.existing_line) {
+            (ImportStatus::New, _) => data.items.push(PhraseItem::Entry(imported.entry.clone())),
+            (ImportStatus::Conflict, Some(line)) if replace_conflicts => {
+                if let Some(PhraseItem::Entry(existing)) = data.items.get_mut(line - 1) {
+                    existing.weight = imported.entry.weight;
+                }
+            }
+            _ => {}
+        }
+    }
+    write_phrases(table, &data)?;
+    Ok(preview)
+}
@@ -0,0 +1,466 @@
+//! Custom phrase tables.
+//!
+//! A custom phrase table is a plain `phrase<TAB>code<TAB>weight` text file in
+//! the user dir, loaded by a `table_translator@<ns>` whose `<ns>` config has a
+//! text `db_class` (`stabledb` or `tabledb`); the file is `<user_dict>.txt`.
+//! Most schemas name theirs `custom_phrase`, but any number may coexist.
+
+pub mod export;
+pub mod import;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::git;
+use super::history::snapshot;
+use super::merge::compile_config;
+use super::paths::get_config_path;
+use super::schemas::discover_schemas;
+
+/// The table read when no name is given.
+pub const DEFAULT_TABLE: &str = "custom_phrase";
+const TEXT_DB_CLASSES: [&str; 2] = ["stabledb", "tabledb"];
+/// librime's defaults for `speller/alphabet` and `speller/delimiter`.
+const DEFAULT_ALPHABET: &str = "zyxwvutsrqponmlkjihgfedcba";
+const DEFAULT_DELIMITER: &str = " '";
+
+const DEFAULT_HEADER: &str = "# Rime custom phrase\n# encoding: utf-8\n#\n# format: phrase<TAB>code<TAB>weight\n#\n";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PhraseEntry {
+    pub phrase: String,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i64>,
+    /// The line as read, when it differs from the canonical formatting
+    /// (extra whitespace or columns). Written back as is while it still
+    /// parses to the same entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    /// The line's own terminator, when it isn't the file's `line_ending`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ending: Option<String>,
+}
+
+/// One line of a phrase file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PhraseItem {
+    Entry(PhraseEntry),
+    Comment {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ending: Option<String>,
+    },
+    /// An empty or whitespace-only line.
+    Blank {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ending: Option<String>,
+    },
+    /// A line that is neither a comment nor a valid entry.
+    Unparsed {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ending: Option<String>,
+    },
+}
+
+impl PhraseItem {
+    /// The line's own terminator, if it differs from the file's.
+    fn ending(&self) -> Option<&str> {
+        match self {
+            PhraseItem::Entry(PhraseEntry { ending, .. })
+            | PhraseItem::Comment { ending, .. }
+            | PhraseItem::Blank { ending, .. }
+            | PhraseItem::Unparsed { ending, .. } => ending.as_deref(),
+        }
+    }
+}
+
+/// A phrase file as its lines in order, so writing it back unchanged
+/// reproduces the file byte for byte.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhrasesData {
+    pub items: Vec<PhraseItem>,
+    /// `\n` or `\r\n`, as used by the file; new lines get this one.
+    pub line_ending: String,
+    /// Whether the last line ends with a line ending.
+    pub final_newline: bool,
+}
+
+/// A custom phrase table and the schemas that load it.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhraseTable {
+    /// The `user_dict` name; the file is `<name>.txt`.
+    pub name: String,
+    pub file: String,
+    pub exists: bool,
+    pub schemas: Vec<PhraseTableUse>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhraseTableUse {
+    pub schema_id: String,
+    /// The translator's name space, e.g. `custom_phrase`.
+    pub translator: String,
+}
+
+/// The file of table `name`, which must be a plain name in the user dir.
+fn table_path(name: &str) -> Result<PathBuf, RimeError> {
+    let relative = Path::new(name);
+    let valid = !name.is_empty() && relative.components().all(|c| matches!(c, Component::Normal(_)));
+    if !valid {
+        return Err(RimeError::invalid(relative, "无效的短语表名"));
+    }
+    Ok(get_config_path(&format!("{}.txt", name)))
+}
+
+/// The custom phrase tables of a compiled schema, as `(translator, user_dict)`.
+fn schema_tables(config: &Value) -> Vec<(String, String)> {
+    let translators = config
+        .pointer("/engine/translators")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    translators
+        .into_iter()
+        .filter_map(|t| t.strip_prefix("table_translator@"))
+        .filter_map(|ns| {
+            let ns_config = config.get(ns)?;
+            let db_class = ns_config.get("db_class")?.as_str()?;
+            if !TEXT_DB_CLASSES.contains(&db_class) {
+                return None;
+            }
+            let user_dict = ns_config
+                .get("user_dict")
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .unwrap_or(ns);
+            Some((ns.to_string(), user_dict.to_string()))
+        })
+        .collect()
+}
+
+/// Every custom phrase table referenced by an installed schema, plus
+/// `custom_phrase` itself. Schemas that fail to compile are skipped.
+pub fn list_tables() -> Vec<PhraseTable> {
+    let mut uses: BTreeMap<String, Vec<PhraseTableUse>> = BTreeMap::new();
+    uses.insert(DEFAULT_TABLE.to_string(), Vec::new());
+
+    let (schemas, _) = discover_schemas();
+    for schema in schemas {
+        let Ok(merged) = compile_config(&schema.schema_id) else { continue };
+        for (translator, name) in schema_tables(&merged.config) {
+            uses.entry(name).or_default().push(PhraseTableUse {
+                schema_id: schema.schema_id.clone(),
+                translator,
+            });
+        }
+    }
+
+    uses.into_iter()
+        .filter_map(|(name, schemas)| {
+            let path = table_path(&name).ok()?;
+            Some(PhraseTable {
+                file: path.to_string_lossy().to_string(),
+                exists: path.is_file(),
+                name,
+                schemas,
+            })
+        })
+        .collect()
+}
+
+/// Read and parse the custom phrase table `table`
+pub fn read_phrases(table: &str) -> Result<PhrasesData, RimeError> {
+    let filepath = table_path(table)?;
+    let content = match std::fs::read_to_string(&filepath) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(parse_custom_phrases(DEFAULT_HEADER)),
+        Err(e) => return Err(RimeError::io(&filepath, e)),
+    };
+
+    Ok(parse_custom_phrases(&content))
+}
+
+/// Parse an entry line; `None` unless it has a phrase, a code and at most
+/// a numeric weight.
+fn parse_entry(line: &str) -> Option<PhraseEntry> {
+    let parts: Vec<&str> = line.trim().split('\t').collect();
+    if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() || parts[1].is_empty() {
+        return None;
+    }
+    let weight = match parts.get(2) {
+        Some(w) if !w.is_empty() => Some(w.parse::<i64>().ok()?),
+        _ => None,
+    };
+    let entry = PhraseEntry {
+        phrase: parts[0].to_string(),
+        code: parts[1].to_string(),
+        weight,
+        raw: None,
+        ending: None,
+    };
+    let raw = Some(line.to_string()).filter(|l| *l != format_entry(&entry));
+    Some(PhraseEntry { raw, ..entry })
+}
+
+fn format_entry(entry: &PhraseEntry) -> String {
+    let mut line = format!("{}\t{}", entry.phrase, entry.code);
+    if let Some(w) = entry.weight {
+        line.push('\t');
+        line.push_str(&w.to_string());
+    }
+    line
+}
+
+fn parse_custom_phrases(content: &str) -> PhrasesData {
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let items = content
+        .split_inclusive('\n')
+        .map(|line| {
+            let text = line.strip_suffix("\r\n").or_else(|| line.strip_suffix('\n')).unwrap_or(line);
+            // Mixed files keep each line's terminator; the last line may have none.
+            let ending = Some(&line[text.len()..])
+                .filter(|e| !e.is_empty() && *e != line_ending)
+                .map(str::to_string);
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                PhraseItem::Blank { text: text.to_string(), ending }
+            } else if trimmed.starts_with('#') {
+                PhraseItem::Comment { text: text.to_string(), ending }
+            } else if let Some(entry) = parse_entry(text) {
+                PhraseItem::Entry(PhraseEntry { ending, ..entry })
+            } else {
+                PhraseItem::Unparsed { text: text.to_string(), ending }
+            }
+        })
+        .collect();
+
+    PhrasesData {
+        items,
+        line_ending: line_ending.to_string(),
+        final_newline: content.ends_with('\n'),
+    }
+}
+
+/// The line an item is written as.
+fn item_line(item: &PhraseItem) -> String {
+    match item {
+        PhraseItem::Entry(entry) => {
+            let canonical = PhraseEntry { raw: None, ending: None, ..entry.clone() };
+            match &entry.raw {
+                Some(raw) if parse_entry(raw).map(|e| PhraseEntry { raw: None, ..e }).as_ref() == Some(&canonical) => {
+                    raw.clone()
+                }
+                _ => format_entry(entry),
+            }
+        }
+        PhraseItem::Comment { text, .. } | PhraseItem::Blank { text, .. } | PhraseItem::Unparsed { text, .. } => text.clone(),
+    }
+}
+
+fn serialize_custom_phrases(data: &PhrasesData) -> Result<String, String> {
+    let line_ending = match data.line_ending.as_str() {
+        "\r\n" => "\r\n",
+        _ => "\n",
+    };
+    let mut content = String::new();
+    for (i, item) in data.items.iter().enumerate() {
+        if let PhraseItem::Entry(entry) = item {
+            if entry.phrase.contains(['\t', '\r', '\n']) || entry.code.contains(['\t', '\r', '\n']) {
+                return Err(format!("第 {} 行: 短语和编码不能包含制表符或换行", i + 1));
+            }
+        }
+        let line = item_line(item);
+        if line.contains('\n') {
+            return Err(format!("第 {} 行: 不能包含换行", i + 1));
+        }
+        content.push_str(&line);
+        if i + 1 < data.items.len() || data.final_newline {
+            content.push_str(item.ending().filter(|e| matches!(*e, "\n" | "\r\n")).unwrap_or(line_ending));
+        }
+    }
+    Ok(content)
+}
+
+/// Serialize and atomically write the custom phrase table `table`
+pub fn write_phrases(table: &str, data: &PhrasesData) -> Result<(), RimeError> {
+    let filepath = table_path(table)?;
+    let dir = filepath.parent().unwrap_or(Path::new("."));
+
+    std::fs::create_dir_all(dir).map_err(|e| RimeError::io(dir, e))?;
+
+    // Refuse to replace a file we could not have read back (e.g. not UTF-8)
+    let previous = match std::fs::read_to_string(&filepath) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(RimeError::io(&filepath, e)),
+    };
+
+    let content = serialize_custom_phrases(data).map_err(|msg| RimeError::invalid(&filepath, msg))?;
+    if content == previous {
+        return Ok(());
+    }
+
+    snapshot(&filepath)?;
+    write_atomic(&filepath, &content)?;
+
+    git::record(&[&filepath], &git::describe_lines(table, &previous, &content));
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    /// The line won't work as intended, or can't be read back.
+    Error,
+    /// The line works but is probably a mistake.
+    Warning,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticKind {
+    Unparsed,
+    ControlCharacter,
+    InvalidCode,
+    Duplicate,
+    ConflictingWeight,
+}
+
+/// A problem with one line; line numbers are 1-based.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhraseDiagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub message: String,
+    /// The earlier line a duplicate or conflict refers to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub related_line: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhraseValidation {
+    /// The schema whose speller the codes were checked against.
+    pub schema_id: Option<String>,
+    pub alphabet: String,
+    pub delimiter: String,
+    pub diagnostics: Vec<PhraseDiagnostic>,
+}
+
+/// Why a line didn't parse as an entry.
+fn unparsed_reason(text: &str) -> &'static str {
+    let parts: Vec<&str> = text.trim().split('\t').collect();
+    if parts.len() < 2 || parts[1].is_empty() {
+        "缺少编码, 短语和编码之间应以制表符分隔"
+    } else if parts[0].is_empty() {
+        "缺少短语"
+    } else if parts.len() > 3 {
+        "列数过多, 格式应为 短语<TAB>编码<TAB>权重"
+    } else {
+        "权重必须是整数"
+    }
+}
+
+/// The speller alphabet and delimiter of `schema_id`, or librime's defaults.
+fn speller_chars(schema_id: Option<&str>) -> Result<(String, String), RimeError> {
+    let config = match schema_id {
+        Some(id) => Some(compile_config(id)?.config),
+        None => None,
+    };
+    let get = |key: &str, default: &str| {
+        config
+            .as_ref()
+            .and_then(|c| c.pointer(&format!("/speller/{}", key)))
+            .and_then(|v| v.as_str())
+            .unwrap_or(default)
+            .to_string()
+    };
+    Ok((get("alphabet", DEFAULT_ALPHABET), get("delimiter", DEFAULT_DELIMITER)))
+}
+
+/// Check a phrase table before saving. Codes are checked against the speller
+/// of `schema_id`, or else of the first schema that loads `table`.
+pub fn validate(table: &str, schema_id: Option<&str>, data: &PhrasesData) -> Result<PhraseValidation, RimeError> {
+    let schema_id = match schema_id {
+        Some(id) => Some(id.to_string()),
+        None => list_tables()
+            .into_iter()
+            .find(|t| t.name == table)
+            .and_then(|t| t.schemas.into_iter().next())
+            .map(|u| u.schema_id),
+    };
+    let (alphabet, delimiter) = speller_chars(schema_id.as_deref())?;
+
+    let mut diagnostics = Vec::new();
+    let mut seen: HashMap<(&str, &str), (usize, Option<i64>)> = HashMap::new();
+    for (i, item) in data.items.iter().enumerate() {
+        let line = i + 1;
+        let mut report = |severity, kind, message: String, related_line| {
+            diagnostics.push(PhraseDiagnostic { line, severity, kind, message, related_line })
+        };
+        let entry = match item {
+            PhraseItem::Entry(entry) => entry,
+            PhraseItem::Unparsed { text, .. } => {
+                report(Severity::Error, DiagnosticKind::Unparsed, unparsed_reason(text).to_string(), None);
+                continue;
+            }
+            PhraseItem::Comment { .. } | PhraseItem::Blank { .. } => continue,
+        };
+
+        if entry.phrase.contains(['\t', '\r', '\n']) || entry.code.contains(['\t', '\r', '\n']) {
+            report(
+                Severity::Error,
+                DiagnosticKind::ControlCharacter,
+                "短语和编码不能包含制表符或换行".into(),
+                None,
+            );
+        }
+        let invalid: BTreeSet<char> = entry
+            .code
+            .chars()
+            .filter(|c| !alphabet.contains(*c) && !delimiter.contains(*c) && !c.is_control())
+            .collect();
+        if !invalid.is_empty() {
+            let chars: String = invalid.iter().map(|c| format!("`{}`", c)).collect::<Vec<_>>().join(" ");
+            report(
+                Severity::Error,
+                DiagnosticKind::InvalidCode,
+                format!("编码含有方案字母表之外的字符 {}, 无法输入", chars),
+                None,
+            );
+        }
+
+        match seen.get(&(entry.phrase.as_str(), entry.code.as_str())) {
+            Some(&(first, weight)) if weight == entry.weight => report(
+                Severity::Warning,
+                DiagnosticKind::Duplicate,
+                format!("与第 {} 行重复", first),
+                Some(first),
+            ),
+            Some(&(first, _)) => report(
+                Severity::Warning,
+                DiagnosticKind::ConflictingWeight,
+                format!("与第 {} 行的短语和编码相同, 但权重不同", first),
+                Some(first),
+            ),
+            None => {
+                seen.insert((&entry.phrase, &entry.code), (line, entry.weight));
+            }
+        }
+    }
+
+    Ok(PhraseValidation { schema_id, alphabet, delimiter, diagnostics })
+}
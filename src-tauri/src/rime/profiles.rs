@@ -0,0 +1,135 @@
+//! Named Rime user directories the app can switch between.
+//!
+//! The registry lives in the app data dir as `profiles.json`. When a profile
+//! is active, `paths::get_rime_config_dir` resolves to its directory, so every
+//! command operates on it.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::paths::invalidate_config_dir;
+
+/// Same directory Tauri uses as `app_data_dir` for this app's identifier.
+const APP_IDENTIFIER: &str = "com.rime.config";
+const REGISTRY_FILE: &str = "profiles.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    pub config_dir: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRegistry {
+    pub active: Option<String>,
+    pub profiles: Vec<Profile>,
+}
+
+impl ProfileRegistry {
+    fn find_mut(&mut self, id: &str) -> Result<&mut Profile, RimeError> {
+        self.profiles
+            .iter_mut()
+            .find(|p| p.id == id)
+            .ok_or_else(|| RimeError::invalid(&registry_path(), format!("未找到配置档案: {}", id)))
+    }
+}
+
+fn registry_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_default()
+        .join(APP_IDENTIFIER)
+        .join(REGISTRY_FILE)
+}
+
+/// Load the registry. A missing file is an empty registry.
+pub fn load_registry() -> Result<ProfileRegistry, RimeError> {
+    let path = registry_path();
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| RimeError::json(&path, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ProfileRegistry::default()),
+        Err(e) => Err(RimeError::io(&path, e)),
+    }
+}
+
+fn save_registry(registry: &ProfileRegistry) -> Result<(), RimeError> {
+    let path = registry_path();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir).map_err(|e| RimeError::io(dir, e))?;
+
+    let content = serde_json::to_string_pretty(registry)
+        .map_err(|e| RimeError::invalid(&path, e.to_string()))?;
+    write_atomic(&path, content)?;
+    invalidate_config_dir();
+    Ok(())
+}
+
+/// The active profile's directory, if a profile is active and still registered.
+pub fn active_config_dir() -> Option<PathBuf> {
+    let registry = load_registry().ok()?;
+    let active = registry.active.as_deref()?;
+    registry
+        .profiles
+        .iter()
+        .find(|p| p.id == active)
+        .map(|p| PathBuf::from(&p.config_dir))
+}
+
+fn validate(name: &str, config_dir: Option<&str>) -> Result<(), RimeError> {
+    if name.trim().is_empty() {
+        return Err(RimeError::invalid(&registry_path(), "名称不能为空"));
+    }
+    if let Some(dir) = config_dir {
+        if !Path::new(dir).is_dir() {
+            return Err(RimeError::invalid(Path::new(dir), "目录不存在"));
+        }
+    }
+    Ok(())
+}
+
+pub fn add_profile(name: &str, config_dir: &str) -> Result<Profile, RimeError> {
+    validate(name, Some(config_dir))?;
+    let mut registry = load_registry()?;
+    let profile = Profile {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        config_dir: config_dir.to_string(),
+    };
+    registry.profiles.push(profile.clone());
+    save_registry(&registry)?;
+    Ok(profile)
+}
+
+pub fn rename_profile(id: &str, name: &str) -> Result<(), RimeError> {
+    validate(name, None)?;
+    let mut registry = load_registry()?;
+    registry.find_mut(id)?.name = name.trim().to_string();
+    save_registry(&registry)
+}
+
+/// Remove a profile from the registry. Its directory is left untouched.
+pub fn remove_profile(id: &str) -> Result<(), RimeError> {
+    let mut registry = load_registry()?;
+    registry.find_mut(id)?;
+    registry.profiles.retain(|p| p.id != id);
+    if registry.active.as_deref() == Some(id) {
+        registry.active = None;
+    }
+    save_registry(&registry)
+}
+
+/// Make a profile active, or pass `None` to go back to the detected default dir.
+pub fn activate_profile(id: Option<&str>) -> Result<(), RimeError> {
+    let mut registry = load_registry()?;
+    if let Some(id) = id {
+        let dir = PathBuf::from(&registry.find_mut(id)?.config_dir);
+        if !dir.is_dir() {
+            return Err(RimeError::invalid(&dir, "目录不存在"));
+        }
+    }
+    registry.active = id.map(|s| s.to_string());
+    save_registry(&registry)
+}
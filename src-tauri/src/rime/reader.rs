@@ -1,42 +1,35 @@
-use super::paths::{get_config_path, get_base_filename, get_custom_filename};
-use super::yaml_utils::{parse_yaml, parse_yaml_with_colors};
+use serde_json::{Map, Value};
+use super::error::RimeError;
+use super::paths::{get_config_path, get_base_filename, get_custom_filename, resolve_config_path, Layer};
+use super::yaml_utils::read_yaml_file;
 
-/// Read a base config file (e.g., default.yaml or rime_ice.schema.yaml)
-pub fn read_base_config(basename: &str) -> serde_json::Value {
+/// Read a base config file (e.g., default.yaml or rime_ice.schema.yaml) from
+/// the user directory, falling back to the shared data directory.
+///
+/// Returns the layer it was found in, or `None` with an empty map if neither has it.
+pub fn read_base_config(basename: &str) -> Result<(Value, Option<Layer>), RimeError> {
     let filename = get_base_filename(basename);
-    let filepath = get_config_path(&filename);
-    match std::fs::read_to_string(&filepath) {
-        Ok(content) => {
-            if basename == "squirrel" {
-                parse_yaml_with_colors(&content)
-            } else {
-                parse_yaml(&content)
-            }
-        }
-        Err(_) => serde_json::Value::Object(serde_json::Map::new()),
-    }
+    let Some((filepath, layer)) = resolve_config_path(&filename) else {
+        return Ok((Value::Object(Map::new()), None));
+    };
+    let parsed = read_yaml_file(&filepath, basename == "squirrel")?;
+    Ok(match parsed {
+        Some(value) => (value, Some(layer)),
+        None => (Value::Object(Map::new()), None),
+    })
 }
 
 /// Read a custom config file and extract the patch key
-pub fn read_custom_config(basename: &str) -> serde_json::Value {
+pub fn read_custom_config(basename: &str) -> Result<Value, RimeError> {
     let filename = get_custom_filename(basename);
     let filepath = get_config_path(&filename);
-    match std::fs::read_to_string(&filepath) {
-        Ok(content) => {
-            let parsed = if basename == "squirrel" {
-                parse_yaml_with_colors(&content)
-            } else {
-                parse_yaml(&content)
-            };
-            match parsed {
-                serde_json::Value::Object(map) => {
-                    map.get("patch")
-                        .cloned()
-                        .unwrap_or(serde_json::Value::Object(serde_json::Map::new()))
-                }
-                _ => serde_json::Value::Object(serde_json::Map::new()),
-            }
-        }
-        Err(_) => serde_json::Value::Object(serde_json::Map::new()),
+    match read_yaml_file(&filepath, basename == "squirrel")? {
+        Some(Value::Object(map)) => match map.get("patch") {
+            Some(Value::Object(patch)) => Ok(Value::Object(patch.clone())),
+            None | Some(Value::Null) => Ok(Value::Object(Map::new())),
+            Some(_) => Err(RimeError::invalid(&filepath, "patch 必须是映射")),
+        },
+        Some(Value::Null) | None => Ok(Value::Object(Map::new())),
+        Some(_) => Err(RimeError::invalid(&filepath, "文件顶层必须是映射")),
     }
 }
@@ -0,0 +1,322 @@
+//! The enabled schemas (`schema_list` in default.yaml) and what they need.
+//!
+//! Changes are written as a `schema_list` patch in default.custom.yaml.
+//! Before a schema is enabled, everything it loads is looked up in the user
+//! and shared dirs: the schemas in `schema/dependencies`, the dictionary of
+//! every translator with its `import_tables` chain, Lua modules, and the
+//! OpenCC configs of simplifiers with the files they reference.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use super::dict::missing_tables;
+use super::error::RimeError;
+use super::merge::compile_config;
+use super::paths::{get_base_filename, resolve_config_path};
+use super::reader::read_custom_config;
+use super::schemas::LuaComponent;
+use super::writer::write_custom_config;
+use super::yaml_utils::read_yaml_file;
+
+const ENGINE_LISTS: [&str; 4] = ["processors", "segmentors", "translators", "filters"];
+/// What a `simplifier` uses without an `opencc_config`.
+const DEFAULT_OPENCC_CONFIG: &str = "t2s.json";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AssetKind {
+    Schema,
+    Dictionary,
+    Lua,
+    Opencc,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingAsset {
+    pub kind: AssetKind,
+    pub name: String,
+    /// The file looked for, relative to the user or shared dir.
+    pub file: String,
+    /// What asked for it, e.g. `rime_ice: translator/dictionary`.
+    pub required_by: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyReport {
+    pub schema_id: String,
+    /// The schema and its dependencies, in the order they were checked.
+    pub schemas: Vec<String>,
+    pub missing: Vec<MissingAsset>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaListChange {
+    /// The schema list after the change, or unchanged if it was blocked.
+    pub schema_list: Vec<String>,
+    pub applied: bool,
+    /// Why an enable was blocked.
+    pub missing: Vec<MissingAsset>,
+}
+
+pub fn schema_ids_of(default_config: &Value) -> Vec<String> {
+    default_config
+        .get("schema_list")
+        .and_then(|v| v.as_array())
+        .map(|list| {
+            list.iter()
+                .filter_map(|item| item.get("schema").and_then(|v| v.as_str()))
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Schema ids listed in the effective `default` config.
+pub fn enabled_schemas() -> Result<Vec<String>, RimeError> {
+    Ok(schema_ids_of(&compile_config("default")?.config))
+}
+
+/// The engine components of a compiled schema, e.g. `table_translator@custom_phrase`.
+fn components(config: &Value) -> Vec<&str> {
+    ENGINE_LISTS
+        .iter()
+        .filter_map(|list| config.pointer(&format!("/engine/{}", list)))
+        .filter_map(|v| v.as_array())
+        .flatten()
+        .filter_map(|v| v.as_str())
+        .collect()
+}
+
+fn exists(file: &str) -> bool {
+    resolve_config_path(file).is_some()
+}
+
+/// The files a Lua component loads: `lua_filter@*module` is `lua/module.lua`
+/// (or `lua/module/init.lua`), the older `lua_filter@function` is a global
+/// in `rime.lua`.
+fn lua_files(component: &LuaComponent) -> Vec<String> {
+    if component.module {
+        let module = component.name.replace('.', "/");
+        vec![format!("lua/{}.lua", module), format!("lua/{}/init.lua", module)]
+    } else {
+        vec!["rime.lua".to_string()]
+    }
+}
+
+/// The data files an OpenCC config references, relative to `opencc/`.
+fn opencc_files(config: &Value, out: &mut Vec<String>) {
+    match config {
+        Value::Object(map) => {
+            if map.contains_key("type") {
+                if let Some(file) = map.get("file").and_then(|v| v.as_str()) {
+                    out.push(file.to_string());
+                }
+            }
+            map.values().for_each(|v| opencc_files(v, out));
+        }
+        Value::Array(items) => items.iter().for_each(|v| opencc_files(v, out)),
+        _ => {}
+    }
+}
+
+/// Look up everything `schema_id` loads, without changing anything.
+pub fn check_dependencies(schema_id: &str) -> Result<DependencyReport, RimeError> {
+    let mut schemas = Vec::new();
+    let mut missing = Vec::new();
+    let mut seen_dicts = HashSet::new();
+    let mut seen_files = HashSet::new();
+    let mut pending = vec![(schema_id.to_string(), "schema_list".to_string())];
+
+    while let Some((id, required_by)) = pending.pop() {
+        if schemas.contains(&id) {
+            continue;
+        }
+        let file = get_base_filename(&id);
+        if !exists(&file) {
+            missing.push(MissingAsset { kind: AssetKind::Schema, name: id, file, required_by });
+            continue;
+        }
+        let config = compile_config(&id)?.config;
+        schemas.push(id.clone());
+
+        let dependencies = config
+            .pointer("/schema/dependencies")
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        pending.extend(
+            dependencies
+                .into_iter()
+                .rev()
+                .map(|d| (d.to_string(), format!("{}: schema/dependencies", id))),
+        );
+
+        let components = components(&config);
+        let mut name_spaces = vec!["translator"];
+        if components.contains(&"reverse_lookup_translator") {
+            name_spaces.push("reverse_lookup");
+        }
+        name_spaces.extend(components.iter().filter_map(|c| c.split_once('@')).map(|(_, ns)| ns));
+        for ns in name_spaces {
+            let Some(dict) = config.pointer(&format!("/{}/dictionary", ns)).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            if dict.is_empty() || !seen_dicts.insert(dict.to_string()) {
+                continue;
+            }
+            for table in missing_tables(dict) {
+                let required_by = match table.imported_by {
+                    Some(importer) => format!("{}.dict.yaml: import_tables", importer),
+                    None => format!("{}: {}/dictionary", id, ns),
+                };
+                missing.push(MissingAsset {
+                    kind: AssetKind::Dictionary,
+                    file: format!("{}.dict.yaml", table.name),
+                    name: table.name,
+                    required_by,
+                });
+            }
+        }
+
+        for component in &components {
+            let Some(lua) = LuaComponent::parse(component) else { continue };
+            let candidates = lua_files(&lua);
+            if candidates.iter().any(|f| exists(f)) || !seen_files.insert(candidates[0].clone()) {
+                continue;
+            }
+            missing.push(MissingAsset {
+                kind: AssetKind::Lua,
+                name: lua.name,
+                file: candidates[0].clone(),
+                required_by: format!("{}: {}", id, component),
+            });
+        }
+
+        for component in &components {
+            let ns = match component.split_once('@') {
+                Some(("simplifier", ns)) => ns,
+                None if *component == "simplifier" => "simplifier",
+                _ => continue,
+            };
+            let opencc_config = config
+                .pointer(&format!("/{}/opencc_config", ns))
+                .and_then(|v| v.as_str())
+                .unwrap_or(DEFAULT_OPENCC_CONFIG);
+            let file = format!("opencc/{}", opencc_config);
+            if !seen_files.insert(file.clone()) {
+                continue;
+            }
+            let required_by = format!("{}: {}/opencc_config", id, ns);
+            let Some((path, _)) = resolve_config_path(&file) else {
+                missing.push(MissingAsset {
+                    kind: AssetKind::Opencc,
+                    name: opencc_config.to_string(),
+                    file,
+                    required_by,
+                });
+                continue;
+            };
+            let mut data_files = Vec::new();
+            if let Ok(Some(parsed)) = read_yaml_file(&path, false) {
+                opencc_files(&parsed, &mut data_files);
+            }
+            for data in data_files {
+                let data_file = format!("opencc/{}", data);
+                if !exists(&data_file) && seen_files.insert(data_file.clone()) {
+                    missing.push(MissingAsset {
+                        kind: AssetKind::Opencc,
+                        name: data,
+                        file: data_file,
+                        required_by: format!("opencc/{}", opencc_config),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(DependencyReport {
+        schema_id: schema_id.to_string(),
+        schemas,
+        missing,
+    })
+}
+
+/// Replace `schema_list` in default.custom.yaml, dropping patches that
+/// append to or edit it, so the list is exactly `ids`.
+fn write_schema_list(ids: &[String]) -> Result<(), RimeError> {
+    let mut patch = match read_custom_config("default")? {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    patch.retain(|key, _| key != "schema_list" && !key.starts_with("schema_list/"));
+    let list = ids
+        .iter()
+        .map(|id| {
+            let mut item = Map::new();
+            item.insert("schema".into(), Value::String(id.clone()));
+            Value::Object(item)
+        })
+        .collect();
+    patch.insert("schema_list".into(), Value::Array(list));
+    write_custom_config("default", &Value::Object(patch))
+}
+
+fn applied(schema_list: Vec<String>) -> SchemaListChange {
+    SchemaListChange { schema_list, applied: true, missing: vec![] }
+}
+
+/// Enable `schema_id` at `position` (default: last) if everything it needs
+/// is installed; otherwise nothing is written and the missing files are listed.
+pub fn enable_schema(schema_id: &str, position: Option<usize>) -> Result<SchemaListChange, RimeError> {
+    let mut list = enabled_schemas()?;
+    let report = check_dependencies(schema_id)?;
+    if !report.missing.is_empty() {
+        return Ok(SchemaListChange { schema_list: list, applied: false, missing: report.missing });
+    }
+
+    list.retain(|id| id != schema_id);
+    let position = position.unwrap_or(list.len()).min(list.len());
+    list.insert(position, schema_id.to_string());
+    write_schema_list(&list)?;
+    Ok(applied(list))
+}
+
+pub fn disable_schema(schema_id: &str) -> Result<SchemaListChange, RimeError> {
+    let mut list = enabled_schemas()?;
+    if !list.iter().any(|id| id == schema_id) {
+        return Ok(SchemaListChange { schema_list: list, applied: false, missing: vec![] });
+    }
+    list.retain(|id| id != schema_id);
+    if list.is_empty() {
+        return Err(RimeError::invalid(
+            Path::new("default.custom.yaml"),
+            "至少需要保留一个方案",
+        ));
+    }
+    write_schema_list(&list)?;
+    Ok(applied(list))
+}
+
+/// Reorder the enabled schemas; `ids` must list exactly the enabled ones.
+pub fn reorder_schemas(ids: &[String]) -> Result<SchemaListChange, RimeError> {
+    let list = enabled_schemas()?;
+    let mut expected = list.clone();
+    let mut given = ids.to_vec();
+    expected.sort();
+    given.sort();
+    if expected != given {
+        return Err(RimeError::invalid(
+            Path::new("default.custom.yaml"),
+            "新顺序必须恰好包含当前启用的方案",
+        ));
+    }
+    if list == ids {
+        return Ok(SchemaListChange { schema_list: list, applied: false, missing: vec![] });
+    }
+    write_schema_list(ids)?;
+    Ok(applied(ids.to_vec()))
+}
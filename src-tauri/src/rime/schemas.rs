@@ -1,6 +1,10 @@
 use serde::Serialize;
-use super::paths::get_rime_config_dir;
-use super::yaml_utils::parse_yaml;
+use std::collections::HashSet;
+use super::algebra::{algebra_of, has_fuzzy_pinyin};
+use super::error::RimeError;
+use super::merge::compile_config;
+use super::paths::{get_rime_config_dir, get_shared_data_dir, Layer};
+use super::yaml_utils::read_yaml_file;
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -12,6 +16,97 @@ pub struct SchemaMetadata {
     pub description: String,
     pub switches: Vec<SchemaSwitch>,
     pub has_fuzzy_pinyin: bool,
+    /// `shared` for schemas that ship with the frontend, `user` for the user's own.
+    pub layer: Layer,
+    pub engine: SchemaEngine,
+    pub speller: SchemaSpeller,
+    pub translator: SchemaTranslator,
+    /// `schema/dependencies`: schemas this one loads, e.g. for reverse lookup.
+    pub dependencies: Vec<String>,
+    pub presets: SchemaPresets,
+    /// The Lua components among the engine's, in engine order.
+    pub lua_components: Vec<LuaComponent>,
+}
+
+/// The component lists of `engine`, as written (`name@name_space`).
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SchemaEngine {
+    pub processors: Vec<String>,
+    pub segmentors: Vec<String>,
+    pub translators: Vec<String>,
+    pub filters: Vec<String>,
+}
+
+/// `speller` settings; `None` where the schema relies on librime's default.
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaSpeller {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alphabet: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_code_length: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SchemaTranslator {
+    /// `translator/dictionary`, the dictionary name without `.dict.yaml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<String>,
+    /// `translator/prism`, when it differs from the dictionary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prism: Option<String>,
+}
+
+/// Shared config the schema imports, e.g. `default` or `symbols`.
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaPresets {
+    /// `key_binder/import_preset`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_binder: Option<String>,
+    /// `punctuator/import_preset`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub punctuator: Option<String>,
+}
+
+/// A librime-lua component such as `lua_filter@*search@radical_lookup`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LuaComponent {
+    /// `lua_processor`, `lua_segmentor`, `lua_translator` or `lua_filter`.
+    pub kind: String,
+    /// The module (`@*name`) or, in the older style, the global in rime.lua.
+    pub name: String,
+    /// Whether `name` is a module under `lua/` rather than a rime.lua global.
+    pub module: bool,
+    /// The config name space, when given after the module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_space: Option<String>,
+}
+
+impl LuaComponent {
+    pub fn parse(component: &str) -> Option<Self> {
+        let (kind, target) = component.split_once('@')?;
+        if !kind.starts_with("lua_") {
+            return None;
+        }
+        let (module, target) = match target.strip_prefix('*') {
+            Some(rest) => (true, rest),
+            None => (false, target),
+        };
+        let (name, name_space) = match target.split_once('@') {
+            Some((name, ns)) => (name, Some(ns.to_string())),
+            None => (target, None),
+        };
+        Some(LuaComponent {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            module,
+            name_space,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -23,37 +118,61 @@ pub struct SchemaSwitch {
     pub reset: Option<i64>,
 }
 
-/// Discover all *.schema.yaml files and extract metadata.
-pub fn discover_schemas() -> Vec<SchemaMetadata> {
-    let config_dir = get_rime_config_dir();
-    let entries = match std::fs::read_dir(&config_dir) {
-        Ok(e) => e,
-        Err(_) => return vec![],
-    };
-
+/// Discover all *.schema.yaml files in the user and shared data directories
+/// and extract metadata. A user file shadows the shared file of the same name.
+///
+/// Files that cannot be read or parsed are returned as errors next to the
+/// schemas that could, so one broken file doesn't hide the rest.
+pub fn discover_schemas() -> (Vec<SchemaMetadata>, Vec<RimeError>) {
     let mut schemas = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
 
-    for entry in entries.flatten() {
-        let name = entry.file_name().to_string_lossy().to_string();
-        if !name.ends_with(".schema.yaml") {
+    let layers = [
+        (Some(get_rime_config_dir()), Layer::User),
+        (get_shared_data_dir(), Layer::Shared),
+    ];
+    for (dir, layer) in layers {
+        let Some(entries) = dir.and_then(|d| std::fs::read_dir(d).ok()) else {
             continue;
-        }
-        let path = entry.path();
-        if let Ok(content) = std::fs::read_to_string(&path) {
-            if let Some(meta) = parse_schema_metadata(&content) {
-                schemas.push(meta);
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".schema.yaml") || !seen.insert(name) {
+                continue;
+            }
+            let path = entry.path();
+            match read_yaml_file(&path, false) {
+                Ok(Some(parsed)) => {
+                    let Some(schema_id) = parsed.pointer("/schema/schema_id").and_then(|v| v.as_str()) else {
+                        continue;
+                    };
+                    // A schema whose patch doesn't compile is still listed, as written.
+                    let config = match compile_config(schema_id) {
+                        Ok(merged) => merged.config,
+                        Err(e) => {
+                            errors.push(e);
+                            parsed.clone()
+                        }
+                    };
+                    if let Some(meta) = parse_schema_metadata(&parsed, &config, layer) {
+                        schemas.push(meta);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
             }
         }
     }
 
-    schemas
+    (schemas, errors)
 }
 
-fn parse_schema_metadata(content: &str) -> Option<SchemaMetadata> {
-    let parsed = parse_yaml(content);
-    let obj = parsed.as_object()?;
-
-    let schema_obj = obj.get("schema")?.as_object()?;
+/// Read a schema's metadata. The id, name and version come from the file as
+/// written (`parsed`); everything else from its compiled `config`, so that
+/// `__include`s and the custom patch are taken into account.
+fn parse_schema_metadata(parsed: &serde_json::Value, config: &serde_json::Value, layer: Layer) -> Option<SchemaMetadata> {
+    let schema_obj = parsed.get("schema")?.as_object()?;
     let schema_id = schema_obj.get("schema_id")?.as_str()?.to_string();
 
     let name = schema_obj
@@ -65,8 +184,8 @@ fn parse_schema_metadata(content: &str) -> Option<SchemaMetadata> {
         .get("version")
         .and_then(|v| v.as_str().map(|s| s.to_string()).or_else(|| Some(v.to_string())))
         .unwrap_or_default();
-    let author = schema_obj
-        .get("author")
+    let author = config
+        .pointer("/schema/author")
         .and_then(|v| v.as_array())
         .map(|arr| {
             arr.iter()
@@ -74,14 +193,14 @@ fn parse_schema_metadata(content: &str) -> Option<SchemaMetadata> {
                 .collect()
         })
         .unwrap_or_default();
-    let description = schema_obj
-        .get("description")
+    let description = config
+        .pointer("/schema/description")
         .and_then(|v| v.as_str())
         .unwrap_or("")
         .to_string();
 
     // Parse switches
-    let switches = obj
+    let switches = config
         .get("switches")
         .and_then(|v| v.as_array())
         .map(|arr| {
@@ -103,20 +222,30 @@ fn parse_schema_metadata(content: &str) -> Option<SchemaMetadata> {
         })
         .unwrap_or_default();
 
-    // Check for fuzzy pinyin rules
-    let has_fuzzy_pinyin = obj
-        .get("speller")
-        .and_then(|v| v.as_object())
-        .and_then(|s| s.get("algebra"))
-        .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter().any(|rule| {
-                rule.as_str()
-                    .map(|s| s.starts_with("# - derive/"))
-                    .unwrap_or(false)
-            })
-        })
-        .unwrap_or(false);
+    let has_fuzzy_pinyin = has_fuzzy_pinyin(&algebra_of(config));
+
+    let list = |path: &str| {
+        config
+            .pointer(path)
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default()
+    };
+    let text = |path: &str| config.pointer(path).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    let engine = SchemaEngine {
+        processors: list("/engine/processors"),
+        segmentors: list("/engine/segmentors"),
+        translators: list("/engine/translators"),
+        filters: list("/engine/filters"),
+    };
+    let lua_components = [&engine.processors, &engine.segmentors, &engine.translators, &engine.filters]
+        .into_iter()
+        .flatten()
+        .filter_map(|c| LuaComponent::parse(c))
+        .collect();
+    let dictionary = text("/translator/dictionary").filter(|d| !d.is_empty());
+    let prism = text("/translator/prism").filter(|p| Some(p) != dictionary.as_ref());
 
     Some(SchemaMetadata {
         schema_id,
@@ -126,5 +255,22 @@ fn parse_schema_metadata(content: &str) -> Option<SchemaMetadata> {
         description,
         switches,
         has_fuzzy_pinyin,
+        layer,
+        speller: SchemaSpeller {
+            alphabet: text("/speller/alphabet"),
+            delimiter: text("/speller/delimiter"),
+            max_code_length: config
+                .pointer("/speller/max_code_length")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as u32),
+        },
+        translator: SchemaTranslator { dictionary, prism },
+        dependencies: list("/schema/dependencies"),
+        presets: SchemaPresets {
+            key_binder: text("/key_binder/import_preset"),
+            punctuator: text("/punctuator/import_preset"),
+        },
+        engine,
+        lua_components,
     })
 }
@@ -0,0 +1,171 @@
+//! Rime user data sync.
+//!
+//! librime syncs user dictionaries and config backups through a shared folder:
+//! each installation writes to `<sync_dir>/<installation_id>/` and merges in
+//! what the others wrote. Both keys live in the user dir's installation.yaml;
+//! `sync_dir` defaults to `<user_dir>/sync`.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::git;
+use super::history::snapshot;
+use super::paths::{get_config_path, get_rime_config_dir};
+use super::yaml_edit::update_top_level_key;
+use super::yaml_utils::{parse_yaml, read_yaml_file};
+
+const INSTALLATION_FILE: &str = "installation.yaml";
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSettings {
+    pub installation_id: String,
+    /// The folder Rime syncs through, after applying the default.
+    pub sync_dir: String,
+    /// Whether `sync_dir` is set in installation.yaml rather than defaulted.
+    pub sync_dir_configured: bool,
+}
+
+/// One installation's folder under the sync dir.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallationFolder {
+    pub installation_id: String,
+    pub path: String,
+    /// This machine's own folder.
+    pub current: bool,
+    /// Newest modification time of the files inside, in milliseconds since
+    /// the Unix epoch; `None` for an empty folder.
+    pub last_sync: Option<u64>,
+    /// User dictionary snapshots (`*.userdb.txt`) in the folder.
+    pub userdbs: Vec<String>,
+    pub files: usize,
+}
+
+fn read_installation() -> Result<(PathBuf, serde_json::Map<String, Value>), RimeError> {
+    let path = get_config_path(INSTALLATION_FILE);
+    match read_yaml_file(&path, false)? {
+        Some(Value::Object(map)) => Ok((path, map)),
+        Some(_) => Err(RimeError::invalid(&path, "文件顶层必须是映射")),
+        None => Err(RimeError::invalid(&path, "未找到 installation.yaml, 请先部署一次")),
+    }
+}
+
+/// The effective sync settings of the active config dir.
+pub fn read_settings() -> Result<SyncSettings, RimeError> {
+    let (path, installation) = read_installation()?;
+    let installation_id = installation
+        .get("installation_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| RimeError::invalid(&path, "缺少 installation_id"))?
+        .to_string();
+    let configured = installation
+        .get("sync_dir")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty());
+    let sync_dir = configured
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_rime_config_dir().join("sync"));
+
+    Ok(SyncSettings {
+        installation_id,
+        sync_dir: sync_dir.to_string_lossy().to_string(),
+        sync_dir_configured: configured.is_some(),
+    })
+}
+
+/// Change `installation_id` and/or `sync_dir` in installation.yaml.
+///
+/// `None` leaves a key as is; an empty `sync_dir` removes the key so Rime
+/// falls back to the default. Other lines of the file are kept unchanged.
+pub fn update_settings(installation_id: Option<&str>, sync_dir: Option<&str>) -> Result<SyncSettings, RimeError> {
+    let (path, _) = read_installation()?;
+
+    if let Some(id) = installation_id {
+        let id = id.trim();
+        if id.is_empty() || id.contains(['/', '\\']) || id == "." || id == ".." {
+            return Err(RimeError::invalid(&path, "installation_id 必须是合法的文件夹名"));
+        }
+    }
+    if let Some(dir) = sync_dir.map(str::trim).filter(|d| !d.is_empty()) {
+        if !Path::new(dir).is_absolute() {
+            return Err(RimeError::invalid(Path::new(dir), "sync_dir 必须是绝对路径"));
+        }
+    }
+
+    let mut content = std::fs::read_to_string(&path).map_err(|e| RimeError::io(&path, e))?;
+    let mut changes = Vec::new();
+    if let Some(id) = installation_id {
+        content = update_top_level_key(&content, "installation_id", Some(&Value::from(id.trim())), parse_yaml);
+        changes.push(format!("installation_id {}", id.trim()));
+    }
+    if let Some(dir) = sync_dir {
+        let dir = dir.trim();
+        let value = Some(Value::from(dir)).filter(|_| !dir.is_empty());
+        content = update_top_level_key(&content, "sync_dir", value.as_ref(), parse_yaml);
+        changes.push(format!("sync_dir {}", if dir.is_empty() { "默认" } else { dir }));
+    }
+    if changes.is_empty() {
+        return read_settings();
+    }
+
+    snapshot(&path)?;
+    write_atomic(&path, &content)?;
+    git::record(&[&path], &format!("installation: {}", changes.join(", ")));
+    read_settings()
+}
+
+/// The per-installation folders under the sync dir, this machine's first,
+/// then the most recently synced.
+pub fn list_installations() -> Result<Vec<InstallationFolder>, RimeError> {
+    let settings = read_settings()?;
+    let sync_dir = PathBuf::from(&settings.sync_dir);
+    let entries = match std::fs::read_dir(&sync_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(RimeError::io(&sync_dir, e)),
+    };
+
+    let mut folders: Vec<InstallationFolder> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .map(|entry| {
+            let installation_id = entry.file_name().to_string_lossy().to_string();
+            let mut last_sync = None;
+            let mut userdbs = Vec::new();
+            let mut files = 0;
+            for file in std::fs::read_dir(entry.path()).into_iter().flatten().flatten() {
+                let Ok(meta) = file.metadata() else { continue };
+                if !meta.is_file() {
+                    continue;
+                }
+                files += 1;
+                let name = file.file_name().to_string_lossy().to_string();
+                if name.ends_with(".userdb.txt") {
+                    userdbs.push(name);
+                }
+                let modified = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_millis() as u64);
+                last_sync = last_sync.max(modified);
+            }
+            userdbs.sort();
+            InstallationFolder {
+                current: installation_id == settings.installation_id,
+                path: entry.path().to_string_lossy().to_string(),
+                installation_id,
+                last_sync,
+                userdbs,
+                files,
+            }
+        })
+        .collect();
+
+    folders.sort_by_key(|f| (!f.current, std::cmp::Reverse(f.last_sync)));
+    Ok(folders)
+}
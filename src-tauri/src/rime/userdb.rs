@@ -0,0 +1,378 @@
+//! Rime user dictionaries in their text snapshot form (`*.userdb.txt`).
+//!
+//! The learned words themselves live in LevelDB folders (`*.userdb/`), which
+//! librime dumps to text snapshots in `<sync_dir>/<installation_id>/` on every
+//! sync and merges back in from all installations. A snapshot looks like
+//! this, with tab-separated fields:
+//!
+//! ```text
+//! # Rime user dictionary
+//! #@/db_name  luna_pinyin.userdb
+//! #@/tick  1024
+//! ni hao   你好  c=3 d=2.8 t=1020
+//! ```
+//!
+//! `c` is the commit count (negative marks a deleted word), `d` the decaying
+//! weight and `t` the tick (the dictionary's commit counter) of the last use.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::history::snapshot;
+use super::paths::get_rime_config_dir;
+use super::sync;
+
+const HEADER: &str = "# Rime user dictionary";
+const METADATA_PREFIX: &str = "#@";
+const SNAPSHOT_SUFFIX: &str = ".userdb.txt";
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDbEntry {
+    pub code: String,
+    pub phrase: String,
+    pub commits: i64,
+    pub dee: f64,
+    pub tick: u64,
+}
+
+impl UserDbEntry {
+    pub fn deleted(&self) -> bool {
+        self.commits < 0
+    }
+}
+
+/// A parsed snapshot. Metadata keys keep their leading `/` (e.g. `/tick`).
+#[derive(Debug, Clone, Default)]
+pub struct UserDb {
+    pub metadata: Vec<(String, String)>,
+    pub entries: Vec<UserDbEntry>,
+}
+
+impl UserDb {
+    fn meta(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn set_meta(&mut self, key: &str, value: String) {
+        match self.metadata.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.metadata.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn db_name(&self) -> Option<&str> {
+        self.meta("/db_name")
+    }
+
+    /// The dictionary's commit counter at the time of the snapshot.
+    pub fn tick(&self) -> u64 {
+        self.meta("/tick").and_then(|t| t.parse().ok()).unwrap_or(0)
+    }
+}
+
+/// A snapshot file as listed for the UI.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDbInfo {
+    pub path: String,
+    pub db_name: String,
+    /// The sync folder it belongs to; `None` for snapshots in the user dir.
+    pub installation_id: Option<String>,
+    /// This machine's own snapshot.
+    pub current: bool,
+    pub tick: u64,
+    pub entries: usize,
+    pub deleted: usize,
+}
+
+/// Identifies an entry: the same phrase may be learned under several codes.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserDbKey {
+    pub code: String,
+    pub phrase: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UserDbPage {
+    pub total: usize,
+    pub entries: Vec<UserDbEntry>,
+}
+
+/// Parse a snapshot. Errors carry the 1-based line number.
+pub fn parse(content: &str) -> Result<UserDb, (usize, String)> {
+    let mut db = UserDb::default();
+    for (index, line) in content.lines().enumerate() {
+        let line_no = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(meta) = line.strip_prefix(METADATA_PREFIX) {
+            let (key, value) = meta.split_once('\t').unwrap_or((meta, ""));
+            db.metadata.push((key.to_string(), value.to_string()));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split('\t');
+        let (Some(code), Some(phrase)) = (fields.next(), fields.next()) else {
+            return Err((line_no, "缺少词条字段".into()));
+        };
+        let mut entry = UserDbEntry {
+            code: code.to_string(),
+            phrase: phrase.to_string(),
+            commits: 0,
+            dee: 0.0,
+            tick: 0,
+        };
+        for stat in fields.next().unwrap_or("").split_whitespace() {
+            let parsed = match stat.split_once('=') {
+                Some(("c", v)) => v.parse().map(|v| entry.commits = v).is_ok(),
+                Some(("d", v)) => v.parse().map(|v| entry.dee = v).is_ok(),
+                Some(("t", v)) => v.parse().map(|v| entry.tick = v).is_ok(),
+                _ => true,
+            };
+            if !parsed {
+                return Err((line_no, format!("无法解析统计值: {}", stat)));
+            }
+        }
+        db.entries.push(entry);
+    }
+    Ok(db)
+}
+
+/// Serialize a snapshot the way librime writes it, entries in key order.
+pub fn serialize(db: &UserDb) -> String {
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for (key, value) in &db.metadata {
+        out.push_str(&format!("{}{}\t{}\n", METADATA_PREFIX, key, value));
+    }
+    let mut entries: Vec<&UserDbEntry> = db.entries.iter().collect();
+    entries.sort_by(|a, b| (&a.code, &a.phrase).cmp(&(&b.code, &b.phrase)));
+    for e in entries {
+        out.push_str(&format!(
+            "{}\t{}\tc={} d={} t={}\n",
+            e.code, e.phrase, e.commits, e.dee, e.tick
+        ));
+    }
+    out
+}
+
+/// librime's `algo::formula_d`: the weight `da` recorded at tick `ta`,
+/// decayed to tick `t` and added to `d`.
+fn formula_d(d: f64, t: f64, da: f64, ta: f64) -> f64 {
+    d + da * ((ta - t) / 200.0).exp()
+}
+
+/// Merge `theirs` into `ours` the way librime's `UserDbMerger` does on sync:
+/// both sides' weights are decayed to their own dictionary tick, the larger
+/// commit count (by magnitude, so deletions count) and the larger weight win,
+/// and every merged entry gets the larger of the two ticks.
+pub fn merge(ours: &UserDb, theirs: &UserDb) -> UserDb {
+    let our_tick = ours.tick();
+    let their_tick = theirs.tick();
+    let max_tick = our_tick.max(their_tick);
+
+    let mut merged = ours.clone();
+    let mut index: HashMap<(String, String), usize> = merged
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| ((e.code.clone(), e.phrase.clone()), i))
+        .collect();
+
+    for v in &theirs.entries {
+        let mut v = v.clone();
+        if v.tick < their_tick {
+            v.dee = formula_d(0.0, their_tick as f64, v.dee, v.tick as f64);
+        }
+
+        let key = (v.code.clone(), v.phrase.clone());
+        let slot = *index.entry(key).or_insert_with(|| {
+            merged.entries.push(UserDbEntry {
+                commits: 0,
+                dee: 0.0,
+                tick: 0,
+                ..v.clone()
+            });
+            merged.entries.len() - 1
+        });
+        let o = &mut merged.entries[slot];
+        if o.tick < our_tick {
+            o.dee = formula_d(0.0, our_tick as f64, o.dee, o.tick as f64);
+        }
+        if o.commits.abs() < v.commits.abs() {
+            o.commits = v.commits;
+        }
+        o.dee = o.dee.max(v.dee);
+        o.tick = max_tick;
+    }
+
+    merged.set_meta("/tick", max_tick.to_string());
+    merged
+}
+
+/// Where snapshots may be read from or written to: the user dir and the sync dir.
+fn allowed_roots() -> Vec<PathBuf> {
+    let mut roots = vec![get_rime_config_dir()];
+    if let Ok(settings) = sync::read_settings() {
+        roots.push(PathBuf::from(settings.sync_dir));
+    }
+    roots
+}
+
+fn checked_path(path: &str) -> Result<PathBuf, RimeError> {
+    let path = PathBuf::from(path);
+    let inside = allowed_roots().iter().any(|root| path.starts_with(root));
+    let is_snapshot = path.to_string_lossy().ends_with(SNAPSHOT_SUFFIX);
+    if !inside || !is_snapshot || path.components().any(|c| c == std::path::Component::ParentDir) {
+        return Err(RimeError::invalid(&path, "不是配置目录或同步目录中的用户词典快照"));
+    }
+    Ok(path)
+}
+
+fn load(path: &Path) -> Result<UserDb, RimeError> {
+    let content = std::fs::read_to_string(path).map_err(|e| RimeError::io(path, e))?;
+    parse(&content).map_err(|(line, message)| RimeError::parse_line(path, line, message))
+}
+
+fn save(path: &Path, db: &UserDb) -> Result<(), RimeError> {
+    snapshot(path)?;
+    write_atomic(path, serialize(db))
+}
+
+fn info(path: &Path, settings: Option<&sync::SyncSettings>) -> Result<UserDbInfo, RimeError> {
+    let db = load(path)?;
+    let folder = path.parent();
+    let installation_id = settings
+        .filter(|s| folder.and_then(Path::parent) == Some(Path::new(&s.sync_dir)))
+        .and(folder)
+        .and_then(Path::file_name)
+        .map(|n| n.to_string_lossy().to_string());
+    let current = settings.map(|s| Some(&s.installation_id) == installation_id.as_ref()).unwrap_or(false);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    Ok(UserDbInfo {
+        path: path.to_string_lossy().to_string(),
+        db_name: db
+            .db_name()
+            .map(|n| n.trim_end_matches(".userdb").to_string())
+            .unwrap_or_else(|| file_name.trim_end_matches(SNAPSHOT_SUFFIX).to_string()),
+        installation_id,
+        current,
+        tick: db.tick(),
+        entries: db.entries.iter().filter(|e| !e.deleted()).count(),
+        deleted: db.entries.iter().filter(|e| e.deleted()).count(),
+    })
+}
+
+fn snapshots_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.to_string_lossy().ends_with(SNAPSHOT_SUFFIX))
+        .collect();
+    files.sort();
+    files
+}
+
+/// All user dictionary snapshots: those in the user dir, then each sync folder's.
+///
+/// Snapshots that fail to parse are returned as errors next to the rest.
+pub fn list_dictionaries() -> (Vec<UserDbInfo>, Vec<RimeError>) {
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+
+    let settings = sync::read_settings().ok();
+    let mut paths = snapshots_in(&get_rime_config_dir());
+    match sync::list_installations() {
+        Ok(installations) => {
+            for installation in installations {
+                paths.extend(snapshots_in(Path::new(&installation.path)));
+            }
+        }
+        Err(e) => errors.push(e),
+    }
+
+    for path in paths {
+        match info(&path, settings.as_ref()) {
+            Ok(i) => found.push(i),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (found, errors)
+}
+
+/// A page of a snapshot's entries, optionally filtered by a code prefix or
+/// a substring of the phrase.
+pub fn read_entries(
+    path: &str,
+    query: Option<&str>,
+    include_deleted: bool,
+    offset: usize,
+    limit: usize,
+) -> Result<UserDbPage, RimeError> {
+    let db = load(&checked_path(path)?)?;
+    let query = query.map(str::trim).filter(|q| !q.is_empty());
+    let matching: Vec<UserDbEntry> = db
+        .entries
+        .into_iter()
+        .filter(|e| include_deleted || !e.deleted())
+        .filter(|e| query.map(|q| e.code.starts_with(q) || e.phrase.contains(q)).unwrap_or(true))
+        .collect();
+    Ok(UserDbPage {
+        total: matching.len(),
+        entries: matching.into_iter().skip(offset).take(limit).collect(),
+    })
+}
+
+/// Mark learned words as deleted. Returns how many were found.
+///
+/// Rime never drops a word on sync; it keeps a tombstone with a negative
+/// commit count. The tombstone is one larger in magnitude than the live count
+/// so that it wins the merge against the copy in the LevelDB dictionary.
+pub fn delete_entries(path: &str, keys: &[UserDbKey]) -> Result<usize, RimeError> {
+    let path = checked_path(path)?;
+    let mut db = load(&path)?;
+    let mut deleted = 0;
+    for entry in db.entries.iter_mut() {
+        let hit = keys.iter().any(|k| k.code == entry.code && k.phrase == entry.phrase);
+        if hit && !entry.deleted() {
+            entry.commits = -(entry.commits.abs() + 1);
+            deleted += 1;
+        }
+    }
+    if deleted > 0 {
+        save(&path, &db)?;
+    }
+    Ok(deleted)
+}
+
+/// Merge the `sources` snapshots into `target`, in order, and write the result.
+pub fn merge_snapshots(target: &str, sources: &[String]) -> Result<UserDbInfo, RimeError> {
+    let target_path = checked_path(target)?;
+    let mut merged = match std::fs::metadata(&target_path) {
+        Ok(_) => load(&target_path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => UserDb::default(),
+        Err(e) => return Err(RimeError::io(&target_path, e)),
+    };
+    for source in sources {
+        let theirs = load(&checked_path(source)?)?;
+        if merged.db_name().is_none() {
+            merged.metadata = theirs.metadata.clone();
+        }
+        merged = merge(&merged, &theirs);
+    }
+    save(&target_path, &merged)?;
+    info(&target_path, sync::read_settings().ok().as_ref())
+}
@@ -1,39 +1,45 @@
 use std::path::Path;
+use super::error::RimeError;
+use super::fs_utils::write_atomic;
+use super::git;
+use super::history::snapshot;
 use super::paths::{get_config_path, get_custom_filename};
-use super::yaml_utils::serialize_patch;
-
-/// Atomically write a custom.yaml file with backup.
-pub fn write_custom_config(basename: &str, patch: &serde_json::Value) -> Result<(), String> {
+use super::yaml_edit::update_patch;
+use super::yaml_utils::{parse_yaml, parse_yaml_with_colors};
+
+/// Atomically write a custom.yaml file, snapshotting the previous version.
+///
+/// Only the patch keys that changed are rewritten; comments and formatting
+/// elsewhere in an existing file are kept as-is. An existing file that does
+/// not parse is never overwritten.
+pub fn write_custom_config(basename: &str, patch: &serde_json::Value) -> Result<(), RimeError> {
     let filename = get_custom_filename(basename);
     let filepath = get_config_path(&filename);
     let dir = filepath.parent().unwrap_or(Path::new("."));
 
     // Ensure directory exists
-    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
-
-    // Create backup if file exists
-    if filepath.exists() {
-        let bak = filepath.with_extension("yaml.bak");
-        let _ = std::fs::copy(&filepath, &bak);
-    }
+    std::fs::create_dir_all(dir).map_err(|e| RimeError::io(dir, e))?;
 
-    // Serialize the patch
-    let content = serialize_patch(patch);
+    let original = match std::fs::read_to_string(&filepath) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(RimeError::io(&filepath, e)),
+    };
+    let parse = if basename == "squirrel" {
+        parse_yaml_with_colors
+    } else {
+        parse_yaml
+    };
+    let previous = parse(&original).map_err(|e| RimeError::parse(&filepath, e))?;
 
-    // Atomic write: write to temp file, then rename
-    let temp_path = filepath.with_extension(format!(
-        "yaml.tmp.{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis()
-    ));
+    snapshot(&filepath)?;
 
-    std::fs::write(&temp_path, &content)
-        .map_err(|e| format!("Failed to write temp file: {}", e))?;
+    // Splice the patch into the existing document
+    let content = update_patch(&original, patch, parse);
 
-    std::fs::rename(&temp_path, &filepath)
-        .map_err(|e| format!("Failed to rename temp file: {}", e))?;
+    write_atomic(&filepath, content)?;
 
+    let previous_patch = previous.get("patch").cloned().unwrap_or_default();
+    git::record(&[&filepath], &git::describe_patch(basename, &previous_patch, patch));
     Ok(())
 }
@@ -0,0 +1,317 @@
+//! Format-preserving edits of the `patch:` block in `*.custom.yaml`.
+//!
+//! The document is treated as lines. Each top-level entry of the `patch`
+//! mapping is located by indentation and parsed on its own; entries whose
+//! value did not change are left byte-identical, changed entries are
+//! re-serialized in place, removed entries are dropped and new entries are
+//! appended after the last existing one. Comments, blank lines, key order,
+//! quoting and flow/block style of untouched entries survive a save.
+//!
+//! `update_top_level_key` does the same for one key of a top-level mapping,
+//! such as `sync_dir` in installation.yaml.
+
+use serde_json::{Map, Value};
+use super::yaml_utils::{serialize_document, serialize_mapping_entry, serialize_patch};
+
+type YamlParser = fn(&str) -> Result<Value, serde_yaml::Error>;
+
+/// An entry of a block mapping, as a range of line indices.
+struct MappingEntry {
+    key: String,
+    value: Value,
+    start: usize,
+    end: usize,
+}
+
+struct PatchBlock {
+    /// Index of the `patch:` line.
+    header: usize,
+    indent: usize,
+    entries: Vec<MappingEntry>,
+}
+
+/// Rewrite `original` so its `patch` mapping equals `patch`, touching only changed keys.
+///
+/// `parse` must be the same parser the reader uses for this file, so that
+/// unchanged values compare equal (e.g. BGR colors in squirrel.custom.yaml).
+pub fn update_patch(
+    original: &str,
+    patch: &Value,
+    parse: YamlParser,
+) -> String {
+    let empty = serde_json::Map::new();
+    let desired = patch.as_object().unwrap_or(&empty);
+
+    // Safety net: if the line-based edit did not produce the requested patch,
+    // fall back to a full rebuild rather than writing something else.
+    splice_patch(original, desired, parse)
+        .filter(|edited| {
+            let reparsed = match parse(edited) {
+                Ok(v) => v,
+                Err(_) => return false,
+            };
+            match reparsed.get("patch").filter(|v| !v.is_null()) {
+                Some(Value::Object(map)) => map == desired,
+                None => desired.is_empty(),
+                _ => false,
+            }
+        })
+        .unwrap_or_else(|| serialize_patch(patch))
+}
+
+/// Rewrite `original` so its top-level `key` is `value`, or is gone for
+/// `None`, leaving the other lines as they are. Falls back to rewriting the
+/// whole mapping if the edit doesn't reparse to the intended document.
+pub fn update_top_level_key(original: &str, key: &str, value: Option<&Value>, parse: YamlParser) -> String {
+    let mut desired = match parse(original) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    match value {
+        Some(value) => {
+            desired.insert(key.to_string(), value.clone());
+        }
+        None => {
+            desired.shift_remove(key);
+        }
+    }
+
+    splice_top_level_key(original, key, value, parse)
+        .filter(|edited| match parse(edited) {
+            Ok(Value::Object(map)) => map == desired,
+            Ok(Value::Null) => desired.is_empty(),
+            _ => false,
+        })
+        .unwrap_or_else(|| serialize_document(&Value::Object(desired)))
+}
+
+fn splice_top_level_key(original: &str, key: &str, value: Option<&Value>, parse: YamlParser) -> Option<String> {
+    let (lines, newline) = split_lines(original);
+    let entries = find_entries(&lines, 0, lines.len(), 0, parse)?;
+    let mut text = value.map(|v| indent_lines(&serialize_mapping_entry(key, v), "", newline));
+
+    let mut out: Vec<String> = Vec::with_capacity(lines.len() + 1);
+    let mut i = 0;
+    while i < lines.len() {
+        match entries.iter().find(|e| e.start == i && e.key == key) {
+            // The first occurrence takes the new value; duplicates are dropped.
+            Some(entry) => {
+                out.extend(text.take().unwrap_or_default());
+                i = entry.end;
+            }
+            None => {
+                out.push(lines[i].clone());
+                i += 1;
+            }
+        }
+    }
+    out.extend(text.unwrap_or_default());
+    Some(out.concat())
+}
+
+/// The lines of `original`, each ending with a newline, and the newline used.
+fn split_lines(original: &str) -> (Vec<String>, &'static str) {
+    let newline = if original.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = original
+        .split_inclusive('\n')
+        .map(|l| l.to_string())
+        .collect();
+    if let Some(last) = lines.last_mut() {
+        if !last.ends_with('\n') {
+            last.push_str(newline);
+        }
+    }
+    (lines, newline)
+}
+
+fn splice_patch(
+    original: &str,
+    desired: &serde_json::Map<String, Value>,
+    parse: YamlParser,
+) -> Option<String> {
+    let (lines, newline) = split_lines(original);
+
+    let block = match find_patch_block(&lines, parse)? {
+        Some(block) => block,
+        None => {
+            // No block-style patch yet: append a fresh one at the end.
+            let mut out: String = lines
+                .iter()
+                .filter(|l| !is_patch_key_line(l))
+                .cloned()
+                .collect();
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push_str(newline);
+            }
+            out.push_str(&with_newline(&serialize_patch(&Value::Object(desired.clone())), newline));
+            return Some(out);
+        }
+    };
+
+    let indent = " ".repeat(block.indent);
+    let mut replacements: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    let mut written: Vec<&str> = Vec::new();
+
+    for entry in &block.entries {
+        let already = written.contains(&entry.key.as_str());
+        match desired.get(&entry.key) {
+            Some(value) if !already && *value == entry.value => {
+                written.push(&entry.key);
+            }
+            Some(value) if !already => {
+                let text = indent_lines(&serialize_mapping_entry(&entry.key, value), &indent, newline);
+                replacements.push((entry.start, entry.end, text));
+                written.push(&entry.key);
+            }
+            _ => replacements.push((entry.start, entry.end, vec![])),
+        }
+    }
+
+    let additions: Vec<String> = desired
+        .iter()
+        .filter(|(k, _)| !written.contains(&k.as_str()))
+        .flat_map(|(k, v)| indent_lines(&serialize_mapping_entry(k, v), &indent, newline))
+        .collect();
+
+    let insert_at = block
+        .entries
+        .last()
+        .map(|e| e.end)
+        .unwrap_or(block.header + 1);
+
+    let mut out: Vec<String> = Vec::with_capacity(lines.len() + additions.len());
+    let mut i = 0;
+    while i < lines.len() {
+        if i == insert_at {
+            out.extend(additions.iter().cloned());
+        }
+        if let Some((_, end, text)) = replacements.iter().find(|(start, _, _)| *start == i) {
+            out.extend(text.iter().cloned());
+            i = *end;
+            continue;
+        }
+        out.push(lines[i].clone());
+        i += 1;
+    }
+    if insert_at >= lines.len() {
+        out.extend(additions);
+    }
+
+    if desired.is_empty() {
+        out[block.header] = format!("patch: {{}}{}", newline);
+    }
+
+    Some(out.concat())
+}
+
+fn with_newline(text: &str, newline: &str) -> String {
+    if newline == "\n" {
+        text.to_string()
+    } else {
+        text.replace('\n', newline)
+    }
+}
+
+fn indent_lines(text: &str, indent: &str, newline: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| format!("{}{}{}", indent, l, newline))
+        .collect()
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let t = line.trim();
+    t.is_empty() || t.starts_with('#')
+}
+
+fn is_patch_key_line(line: &str) -> bool {
+    if indentation(line) != 0 {
+        return false;
+    }
+    let t = line.trim_end();
+    ["patch:", "\"patch\":", "'patch':"]
+        .iter()
+        .any(|k| t == *k || t.starts_with(&format!("{} ", k)))
+}
+
+/// Locate a block-style `patch:` mapping and its top-level entries.
+///
+/// Returns `Some(None)` when there is no block-style patch to edit and `None`
+/// when an entry could not be parsed on its own.
+fn find_patch_block(lines: &[String], parse: YamlParser) -> Option<Option<PatchBlock>> {
+    let header = match lines.iter().position(|l| is_patch_key_line(l)) {
+        Some(header) => header,
+        None => return Some(None),
+    };
+
+    // Only block style (`patch:` followed by nothing but a comment) is edited in place.
+    let rest = lines[header].trim_end();
+    let after_colon = rest.split_once(':').map(|(_, r)| r.trim()).unwrap_or("");
+    if !after_colon.is_empty() && !after_colon.starts_with('#') {
+        return Some(None);
+    }
+
+    let block_end = (header + 1..lines.len())
+        .find(|&i| !is_blank_or_comment(&lines[i]) && indentation(&lines[i]) == 0)
+        .unwrap_or(lines.len());
+
+    let indent = (header + 1..block_end)
+        .find(|&i| !is_blank_or_comment(&lines[i]))
+        .map(|i| indentation(&lines[i]))
+        .unwrap_or(2);
+
+    let entries = find_entries(lines, header + 1, block_end, indent, parse)?;
+    Some(Some(PatchBlock { header, indent, entries }))
+}
+
+/// The entries of the block mapping at `indent` in `lines[from..to]`, each
+/// parsed on its own. `None` when one doesn't parse as a single entry.
+fn find_entries(lines: &[String], from: usize, to: usize, indent: usize, parse: YamlParser) -> Option<Vec<MappingEntry>> {
+    let is_entry_start = |line: &str| {
+        !is_blank_or_comment(line)
+            && indentation(line) == indent
+            && !line.trim_start().starts_with('-')
+    };
+
+    let mut entries = Vec::new();
+    let mut i = from;
+    while i < to {
+        if !is_entry_start(&lines[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        let mut j = i + 1;
+        while j < to && !is_entry_start(&lines[j]) {
+            if !is_blank_or_comment(&lines[j]) {
+                end = j + 1;
+            }
+            j += 1;
+        }
+
+        let text: String = lines[start..end]
+            .iter()
+            .map(|l| {
+                if indentation(l) >= indent {
+                    &l[indent..]
+                } else {
+                    l.trim_start()
+                }
+            })
+            .collect();
+        let parsed = parse(&text).ok()?;
+        let (key, value) = parsed.as_object()?.iter().next()?;
+        entries.push(MappingEntry {
+            key: key.clone(),
+            value: value.clone(),
+            start,
+            end,
+        });
+        i = j;
+    }
+    Some(entries)
+}
@@ -1,5 +1,7 @@
 use serde_yaml::Value;
 use std::collections::HashSet;
+use std::path::Path;
+use super::error::RimeError;
 
 /// BGR color key names used in Rime squirrel.yaml
 fn bgr_color_keys() -> HashSet<&'static str> {
@@ -24,17 +26,36 @@ fn bgr_color_keys() -> HashSet<&'static str> {
 }
 
 /// Parse YAML content into a serde_json::Value
-pub fn parse_yaml(content: &str) -> serde_json::Value {
-    let yaml_val: serde_yaml::Value = serde_yaml::from_str(content).unwrap_or(Value::Null);
-    yaml_to_json(yaml_val)
+pub fn parse_yaml(content: &str) -> Result<serde_json::Value, serde_yaml::Error> {
+    let yaml_val: serde_yaml::Value = serde_yaml::from_str(content)?;
+    Ok(yaml_to_json(yaml_val))
 }
 
 /// Parse YAML and convert BGR integer colors to "0xHHHHHH" strings
-pub fn parse_yaml_with_colors(content: &str) -> serde_json::Value {
-    let yaml_val: serde_yaml::Value = serde_yaml::from_str(content).unwrap_or(Value::Null);
-    let json_val = yaml_to_json(yaml_val);
+pub fn parse_yaml_with_colors(content: &str) -> Result<serde_json::Value, serde_yaml::Error> {
+    parse_yaml(content).map(convert_bgr_colors)
+}
+
+/// Read and parse a YAML file. A missing file is `Ok(None)`; unreadable or
+/// malformed files are reported with their path and error location.
+pub fn read_yaml_file(path: &Path, with_colors: bool) -> Result<Option<serde_json::Value>, RimeError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(RimeError::io(path, e)),
+    };
+    let parsed = if with_colors {
+        parse_yaml_with_colors(&content)
+    } else {
+        parse_yaml(&content)
+    };
+    parsed.map(Some).map_err(|e| RimeError::parse(path, e))
+}
+
+/// Convert BGR integer colors in an already-parsed config to "0xHHHHHH" strings
+pub fn convert_bgr_colors(val: serde_json::Value) -> serde_json::Value {
     let color_keys = bgr_color_keys();
-    preserve_bgr_colors(json_val, &color_keys)
+    preserve_bgr_colors(val, &color_keys)
 }
 
 /// Recursively convert integer color values to 0xHHHHHH strings
@@ -125,6 +146,18 @@ pub fn serialize_patch(patch: &serde_json::Value) -> String {
     serde_yaml::to_string(&Value::Mapping(wrapper)).unwrap_or_else(|_| "patch: {}\n".into())
 }
 
+/// Serialize a whole document, e.g. a top-level mapping
+pub fn serialize_document(value: &serde_json::Value) -> String {
+    serde_yaml::to_string(&json_to_yaml(value)).unwrap_or_else(|_| "{}\n".into())
+}
+
+/// Serialize a single `key: value` mapping entry (unindented)
+pub fn serialize_mapping_entry(key: &str, value: &serde_json::Value) -> String {
+    let mut entry = serde_yaml::Mapping::new();
+    entry.insert(Value::String(key.into()), json_to_yaml(value));
+    serde_yaml::to_string(&Value::Mapping(entry)).unwrap_or_else(|_| format!("{}: null\n", key))
+}
+
 /// Convert serde_json::Value to serde_yaml::Value
 fn json_to_yaml(json: &serde_json::Value) -> serde_yaml::Value {
     match json {
@@ -4,20 +4,19 @@ import { useState, useMemo } from 'react';
 import { Button } from '@/components/ui/button';
 import { cn } from '@/lib/utils';
 import { serializeCustomPhrases } from '@/lib/phrases/serializer';
-import type { CustomPhrase } from '@/types/phrases';
+import type { CustomPhrasesData } from '@/types/phrases';
 
 interface PhrasePreviewPanelProps {
-  header: string;
-  entries: CustomPhrase[];
+  data: CustomPhrasesData;
 }
 
-export function PhrasePreviewPanel({ header, entries }: PhrasePreviewPanelProps) {
+export function PhrasePreviewPanel({ data }: PhrasePreviewPanelProps) {
   const [isOpen, setIsOpen] = useState(true);
   const [copied, setCopied] = useState(false);
 
   const previewText = useMemo(
-    () => serializeCustomPhrases({ header, entries }),
-    [header, entries]
+    () => serializeCustomPhrases(data),
+    [data]
   );
 
   const handleCopy = async () => {
@@ -1,5 +1,6 @@
 import { useEffect, useCallback, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { formatError } from '@/lib/utils';
 import { useConfigStore } from '@/stores/config-store';
 
 export function useConfig(basename: string) {
@@ -29,7 +30,7 @@ export function useConfig(basename: string) {
         loadConfig(data.base || {}, data.customPatch || {});
       })
       .catch((err) => {
-        setError(String(err));
+        setError(formatError(err));
       })
       .finally(() => {
         setLoading(false);
@@ -43,7 +44,7 @@ export function useConfig(basename: string) {
       markSaved();
       return true;
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(formatError(err));
       return false;
     } finally {
       setLoading(false);
@@ -57,7 +58,7 @@ export function useConfig(basename: string) {
     } catch (err) {
       return {
         success: false,
-        message: err instanceof Error ? err.message : String(err),
+        message: formatError(err),
       };
     }
   }, []);
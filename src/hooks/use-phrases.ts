@@ -1,11 +1,14 @@
-import { useEffect, useCallback, useState } from 'react';
+import { useEffect, useCallback, useMemo, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { formatError } from '@/lib/utils';
 import { usePhrasesStore } from '@/stores/phrases-store';
+import type { CustomPhrase, CustomPhrasesData, PhraseItem } from '@/types/phrases';
 
-export function usePhrases() {
+export function usePhrases(table?: string) {
   const {
-    header,
-    entries,
+    items,
+    lineEnding,
+    finalNewline,
     loadPhrases,
     addEntry,
     updateEntry,
@@ -23,40 +26,50 @@ export function usePhrases() {
 
   const [refreshKey, setRefreshKey] = useState(0);
 
+  const entries = useMemo(
+    () => items.filter((item): item is { kind: 'entry' } & CustomPhrase => item.kind === 'entry'),
+    [items]
+  );
+  const data = useMemo(() => ({ items, lineEnding, finalNewline }), [items, lineEnding, finalNewline]);
+
   useEffect(() => {
     setLoading(true);
-    invoke<{ header: string; entries: { phrase: string; code: string; weight?: number }[] }>('read_phrases')
-      .then((data) => {
-        const entriesWithId = (data.entries || []).map((e) => ({
-          ...e,
+    invoke<CustomPhrasesData>('read_phrases', { table })
+      .then((result) => {
+        const itemsWithId = (result.items || []).map((item) => ({
+          ...item,
           id: crypto.randomUUID(),
-        }));
-        loadPhrases(data.header || '', entriesWithId);
+        })) as PhraseItem[];
+        loadPhrases({ ...result, items: itemsWithId });
       })
       .catch((err) => {
-        setError(String(err));
+        setError(formatError(err));
       })
       .finally(() => {
         setLoading(false);
       });
-  }, [refreshKey, loadPhrases, setError, setLoading]);
+  }, [table, refreshKey, loadPhrases, setError, setLoading]);
 
   const save = useCallback(async () => {
     setLoading(true);
     try {
       await invoke('write_phrases', {
-        header,
-        entries: entries.map(({ id: _id, ...rest }) => rest),
+        table,
+        data: {
+          items: items.map(({ id: _id, ...rest }) => rest),
+          lineEnding,
+          finalNewline,
+        },
       });
       markSaved();
       return true;
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(formatError(err));
       return false;
     } finally {
       setLoading(false);
     }
-  }, [header, entries, markSaved, setError, setLoading]);
+  }, [table, items, lineEnding, finalNewline, markSaved, setError, setLoading]);
 
   const deploy = useCallback(async () => {
     try {
@@ -65,7 +78,7 @@ export function usePhrases() {
     } catch (err) {
       return {
         success: false,
-        message: err instanceof Error ? err.message : String(err),
+        message: formatError(err),
       };
     }
   }, []);
@@ -83,7 +96,7 @@ export function usePhrases() {
   }, []);
 
   return {
-    header,
+    data,
     entries,
     isLoading,
     error,
@@ -1,13 +1,15 @@
-import type { CustomPhrasesData } from '@/types/phrases';
+import type { CustomPhrasesData, PhraseItem } from '@/types/phrases';
 
-export function serializeCustomPhrases(data: CustomPhrasesData): string {
-  const lines: string[] = [data.header.trimEnd()];
-
-  for (const entry of data.entries) {
-    const parts = [entry.phrase, entry.code];
-    if (entry.weight != null) parts.push(String(entry.weight));
-    lines.push(parts.join('\t'));
-  }
+function itemLine(item: PhraseItem): string {
+  if (item.kind !== 'entry') return item.text;
+  if (item.raw != null) return item.raw;
+  const parts = [item.phrase, item.code];
+  if (item.weight != null) parts.push(String(item.weight));
+  return parts.join('\t');
+}
 
-  return lines.join('\n') + '\n';
+export function serializeCustomPhrases(data: CustomPhrasesData): string {
+  const lineEnding = data.lineEnding === '\r\n' ? '\r\n' : '\n';
+  const content = data.items.map(itemLine).join(lineEnding);
+  return data.finalNewline && data.items.length > 0 ? content + lineEnding : content;
 }
@@ -4,3 +4,22 @@ import { twMerge } from "tailwind-merge"
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs))
 }
+
+/**
+ * Format an error rejected by a Tauri command. Rust-side `RimeError`s arrive
+ * as `{ kind, path, line?, column?, message }` objects.
+ */
+export function formatError(err: unknown): string {
+  if (err instanceof Error) return err.message
+  if (err && typeof err === "object" && "message" in err) {
+    const { path, line, column, message } = err as {
+      path?: string
+      line?: number
+      column?: number
+      message: string
+    }
+    const location = line != null ? `:${line}${column != null ? `:${column}` : ""}` : ""
+    return path ? `${path}${location}: ${message}` : message
+  }
+  return String(err)
+}
@@ -6,7 +6,7 @@ import { usePhrases } from '@/hooks/use-phrases';
 
 export default function PhrasesPage() {
   const {
-    header,
+    data,
     entries,
     isLoading,
     isDirty,
@@ -55,7 +55,7 @@ export default function PhrasesPage() {
           />
         </div>
       </div>
-      <PhrasePreviewPanel header={header} entries={entries} />
+      <PhrasePreviewPanel data={data} />
     </div>
   );
 }
@@ -14,6 +14,7 @@ interface SchemaInfo {
   description: string;
   switches: { name: string; states?: string[] }[];
   hasFuzzyPinyin: boolean;
+  layer: 'user' | 'shared';
 }
 
 export default function SchemaPage() {
@@ -82,6 +83,11 @@ export default function SchemaPage() {
                     模糊音
                   </Badge>
                 )}
+                {schema.layer === 'shared' && (
+                  <Badge variant="outline" className="text-[10px]">
+                    内置
+                  </Badge>
+                )}
               </div>
               <Button
                 variant="outline"
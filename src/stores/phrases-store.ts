@@ -1,17 +1,17 @@
 import { create } from 'zustand';
-import type { CustomPhrase } from '@/types/phrases';
+import type { CustomPhrase, CustomPhrasesData, PhraseItem } from '@/types/phrases';
 
 interface PhrasesState {
-  header: string;
-  entries: CustomPhrase[];
-  savedHeader: string;
-  savedEntries: CustomPhrase[];
+  items: PhraseItem[];
+  lineEnding: string;
+  finalNewline: boolean;
+  savedItems: PhraseItem[];
   isLoading: boolean;
   error: string | null;
   searchQuery: string;
 
   // Actions
-  loadPhrases: (header: string, entries: CustomPhrase[]) => void;
+  loadPhrases: (data: CustomPhrasesData) => void;
   addEntry: (entry: CustomPhrase) => void;
   updateEntry: (id: string, updates: Partial<Omit<CustomPhrase, 'id'>>) => void;
   removeEntry: (id: string) => void;
@@ -23,10 +23,15 @@ interface PhrasesState {
   reset: () => void;
 }
 
-function entriesEqual(a: CustomPhrase[], b: CustomPhrase[]): boolean {
+function itemsEqual(a: PhraseItem[], b: PhraseItem[]): boolean {
   if (a.length !== b.length) return false;
   for (let i = 0; i < a.length; i++) {
-    if (a[i].phrase !== b[i].phrase || a[i].code !== b[i].code || a[i].weight !== b[i].weight) {
+    const x = a[i];
+    const y = b[i];
+    if (x.kind !== y.kind) return false;
+    if (x.kind === 'entry' && y.kind === 'entry') {
+      if (x.phrase !== y.phrase || x.code !== y.code || x.weight !== y.weight) return false;
+    } else if (x.kind !== 'entry' && y.kind !== 'entry' && x.text !== y.text) {
       return false;
     }
   }
@@ -34,60 +39,58 @@ function entriesEqual(a: CustomPhrase[], b: CustomPhrase[]): boolean {
 }
 
 export const usePhrasesStore = create<PhrasesState>((set, get) => ({
-  header: '',
-  entries: [],
-  savedHeader: '',
-  savedEntries: [],
+  items: [],
+  lineEnding: '\n',
+  finalNewline: true,
+  savedItems: [],
   isLoading: false,
   error: null,
   searchQuery: '',
 
-  loadPhrases: (header, entries) =>
+  loadPhrases: (data) =>
     set({
-      header,
-      entries: [...entries],
-      savedHeader: header,
-      savedEntries: [...entries],
+      items: [...data.items],
+      lineEnding: data.lineEnding,
+      finalNewline: data.finalNewline,
+      savedItems: [...data.items],
       isLoading: false,
       error: null,
     }),
 
   addEntry: (entry) =>
     set((state) => ({
-      entries: [...state.entries, entry],
+      items: [...state.items, { kind: 'entry', ...entry }],
     })),
 
   updateEntry: (id, updates) =>
     set((state) => ({
-      entries: state.entries.map((e) =>
-        e.id === id ? { ...e, ...updates } : e
+      items: state.items.map((item) =>
+        item.kind === 'entry' && item.id === id ? { ...item, ...updates, raw: undefined } : item
       ),
     })),
 
   removeEntry: (id) =>
     set((state) => ({
-      entries: state.entries.filter((e) => e.id !== id),
+      items: state.items.filter((item) => item.id !== id),
     })),
 
   setSearchQuery: (query) => set({ searchQuery: query }),
 
   markSaved: () =>
     set((state) => ({
-      savedHeader: state.header,
-      savedEntries: [...state.entries],
+      savedItems: [...state.items],
     })),
 
   setLoading: (loading) => set({ isLoading: loading }),
   setError: (error) => set({ error }),
 
   isDirty: () => {
-    const { header, entries, savedHeader, savedEntries } = get();
-    return header !== savedHeader || !entriesEqual(entries, savedEntries);
+    const { items, savedItems } = get();
+    return !itemsEqual(items, savedItems);
   },
 
   reset: () =>
     set((state) => ({
-      header: state.savedHeader,
-      entries: [...state.savedEntries],
+      items: [...state.savedItems],
     })),
 }));
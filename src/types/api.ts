@@ -4,8 +4,11 @@ export interface ApiResponse<T = unknown> {
   error?: string;
 }
 
+export type Layer = 'user' | 'shared';
+
 export interface ConfigResponse {
   base: Record<string, unknown>;
+  baseLayer?: Layer;
   custom: Record<string, unknown>;
   customPatch: Record<string, unknown>;
 }
@@ -14,6 +17,12 @@ export interface HealthResponse {
   platform: string;
   configDir: string;
   configDirExists: boolean;
+  sharedDir?: string;
+  profile?: string;
+  frontend?: {
+    id: string;
+    name: string;
+  };
   installation?: {
     distributionName: string;
     distributionVersion: string;
@@ -22,8 +31,19 @@ export interface HealthResponse {
   };
 }
 
+export interface RimeError {
+  kind: 'io' | 'parse' | 'invalid';
+  path: string;
+  line?: number;
+  column?: number;
+  /** For `invalid`: the data directory `path` is in. */
+  layer?: Layer;
+  message: string;
+}
+
 export interface SchemaListResponse {
   schemas: SchemaMetadata[];
+  errors: RimeError[];
 }
 
 export interface SchemaMetadata {
@@ -34,9 +54,283 @@ export interface SchemaMetadata {
   description: string;
   switches: { name: string; states?: string[]; reset?: number }[];
   hasFuzzyPinyin: boolean;
+  layer: Layer;
+  engine: SchemaEngine;
+  speller: SchemaSpeller;
+  translator: SchemaTranslator;
+  dependencies: string[];
+  presets: SchemaPresets;
+  luaComponents: LuaComponent[];
+}
+
+export interface SchemaEngine {
+  processors: string[];
+  segmentors: string[];
+  translators: string[];
+  filters: string[];
+}
+
+export interface SchemaSpeller {
+  alphabet?: string;
+  delimiter?: string;
+  maxCodeLength?: number;
+}
+
+export interface SchemaTranslator {
+  dictionary?: string;
+  prism?: string;
+}
+
+export interface SchemaPresets {
+  keyBinder?: string;
+  punctuator?: string;
+}
+
+export interface LuaComponent {
+  kind: string;
+  name: string;
+  module: boolean;
+  nameSpace?: string;
 }
 
 export interface DeployResponse {
   success: boolean;
   message: string;
+  frontend?: string;
+  durationMs: number;
+  schemas: SchemaBuildStatus[];
+  warnings: string[];
+  errors: string[];
+}
+
+export interface SchemaBuildStatus {
+  schemaId: string;
+  success: boolean;
+  artifacts: { file: string; updated: boolean }[];
+  warnings: string[];
+  errors: string[];
+}
+
+export interface EffectiveConfigResponse {
+  config: Record<string, unknown>;
+  sources: { file: string; layer: Layer }[];
+}
+
+export interface Profile {
+  id: string;
+  name: string;
+  configDir: string;
+}
+
+export interface ProfileRegistry {
+  active?: string;
+  profiles: Profile[];
+}
+
+export interface CandidateDir {
+  path: string;
+  frontend?: string;
+  frontendName?: string;
+  distribution?: string;
+  installed: boolean;
+  yamlFiles: number;
+}
+
+export interface Snapshot {
+  id: string;
+  file: string;
+  createdAt: number;
+  size: number;
+}
+
+export interface DiffLine {
+  kind: 'equal' | 'insert' | 'delete';
+  oldLine?: number;
+  newLine?: number;
+  text: string;
+}
+
+export interface GitStatus {
+  available: boolean;
+  initialized: boolean;
+}
+
+export interface CommitInfo {
+  id: string;
+  summary: string;
+  message: string;
+  author: string;
+  time: number;
+  files: string[];
+}
+
+export interface FileDiff {
+  file: string;
+  lines: DiffLine[];
+}
+
+export interface SyncSettings {
+  installationId: string;
+  syncDir: string;
+  syncDirConfigured: boolean;
+}
+
+export interface InstallationFolder {
+  installationId: string;
+  path: string;
+  current: boolean;
+  lastSync?: number;
+  userdbs: string[];
+  files: number;
+}
+
+export interface UserDbInfo {
+  path: string;
+  dbName: string;
+  installationId?: string;
+  current: boolean;
+  tick: number;
+  entries: number;
+  deleted: number;
+}
+
+export interface UserDbEntry {
+  code: string;
+  phrase: string;
+  commits: number;
+  dee: number;
+  tick: number;
+}
+
+export interface UserDbPage {
+  total: number;
+  entries: UserDbEntry[];
+}
+
+export interface DictInfo {
+  id: string;
+  name: string;
+  layer: Layer;
+  version: string;
+  sort: string;
+  columns: string[];
+  importTables: string[];
+}
+
+export interface DictEntry {
+  text: string;
+  code?: string;
+  weight?: string;
+  stem?: string;
+}
+
+export interface DictHit extends DictEntry {
+  table: string;
+  line: number;
+}
+
+export interface DictQuery {
+  query?: string;
+  code?: string;
+  text?: string;
+  minWeight?: number;
+  maxWeight?: number;
+}
+
+export interface DictPage {
+  total: number;
+  entries: DictHit[];
+}
+
+export interface MissingAsset {
+  kind: 'schema' | 'dictionary' | 'lua' | 'opencc';
+  name: string;
+  file: string;
+  requiredBy: string;
+}
+
+export interface DependencyReport {
+  schemaId: string;
+  schemas: string[];
+  missing: MissingAsset[];
+}
+
+export interface SchemaListChange {
+  schemaList: string[];
+  applied: boolean;
+  missing: MissingAsset[];
+}
+
+export type AlgebraRule =
+  | { op: 'xform' | 'derive' | 'abbrev' | 'fuzz'; pattern: string; replacement: string }
+  | { op: 'xlit'; from: string; to: string }
+  | { op: 'erase'; pattern: string }
+  | { op: 'unknown'; text: string };
+
+export type ParsedAlgebraRule = AlgebraRule & {
+  raw: string;
+  fuzzy: string[];
+};
+
+export interface FuzzyPair {
+  id: string;
+  from: string;
+  to: string;
+  group: 'initials' | 'finals';
+  forward: string[];
+  reverse: string[];
+}
+
+export interface FuzzyState extends FuzzyPair {
+  forwardEnabled: boolean;
+  reverseEnabled: boolean;
+}
+
+export interface SchemaAlgebra {
+  schemaId: string;
+  rules: ParsedAlgebraRule[];
+  fuzzy: FuzzyState[];
+  patched: boolean;
+  doublePinyin?: string;
+}
+
+export type SpellingKind = 'normal' | 'fuzzy' | 'abbreviation';
+
+export interface SpellingSource {
+  syllable: string;
+  kind: SpellingKind;
+}
+
+export interface Spelling {
+  spelling: string;
+  sources: SpellingSource[];
+}
+
+export interface RuleEffect {
+  raw: string;
+  added: string[];
+  removed: string[];
+  error?: string;
+}
+
+export interface AlgebraSimulation {
+  schemaId: string;
+  dictionary?: string;
+  syllables: string[];
+  rules: RuleEffect[];
+  spellings: Spelling[];
+}
+
+export interface DoublePinyinScheme {
+  id: string;
+  name: string;
+  zh: string;
+  ch: string;
+  sh: string;
+  zeroInitial: 'doubled' | 'leadingO';
+  /** Final and key; a final listed twice can be typed with either key. */
+  finals: [string, string][];
+  alphabet: string;
+  initials: string;
+  algebra: string[];
 }
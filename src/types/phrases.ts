@@ -3,9 +3,86 @@ export interface CustomPhrase {
   phrase: string;
   code: string;
   weight?: number;
+  /** The line as read, when it differs from the canonical formatting. */
+  raw?: string;
+  /** The line's own terminator, when it isn't the file's `lineEnding`. */
+  ending?: string;
 }
 
+/** One line of a phrase file, in file order. */
+export type PhraseItem =
+  | ({ kind: 'entry' } & CustomPhrase)
+  | { id: string; kind: 'comment' | 'blank' | 'unparsed'; text: string; ending?: string };
+
 export interface CustomPhrasesData {
-  header: string;
-  entries: CustomPhrase[];
+  items: PhraseItem[];
+  lineEnding: string;
+  finalNewline: boolean;
+}
+
+export interface PhraseTableUse {
+  schemaId: string;
+  translator: string;
+}
+
+export interface PhraseTable {
+  name: string;
+  file: string;
+  exists: boolean;
+  schemas: PhraseTableUse[];
+}
+
+export type PhraseDiagnosticKind =
+  | 'unparsed'
+  | 'controlCharacter'
+  | 'invalidCode'
+  | 'duplicate'
+  | 'conflictingWeight';
+
+export interface PhraseDiagnostic {
+  line: number;
+  severity: 'error' | 'warning';
+  kind: PhraseDiagnosticKind;
+  message: string;
+  relatedLine?: number;
+}
+
+export interface PhraseValidation {
+  schemaId?: string;
+  alphabet: string;
+  delimiter: string;
+  diagnostics: PhraseDiagnostic[];
+}
+
+export type ImportFormat = 'sogou' | 'qq' | 'baidu' | 'msPinyin' | 'macPlist' | 'csv';
+
+export interface ImportedPhrase {
+  phrase: string;
+  code: string;
+  weight?: number;
+  status: 'new' | 'duplicate' | 'conflict';
+  sourceLine: number;
+  existingLine?: number;
+}
+
+export interface SkippedLine {
+  line: number;
+  text: string;
+  reason: string;
+}
+
+export interface ImportPreview {
+  format: ImportFormat;
+  encoding?: string;
+  entries: ImportedPhrase[];
+  skipped: SkippedLine[];
+}
+
+export type ExportFormat = 'csv' | 'json' | 'macPlist' | 'dict';
+
+export interface ExportSummary {
+  path: string;
+  entries: number;
+  /** Entries left out of a dictionary export. */
+  skipped: SkippedLine[];
 }